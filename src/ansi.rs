@@ -0,0 +1,480 @@
+use std::fmt::Write;
+
+/// Cột xa nhất mà lệnh di chuyển con trỏ được phép tới, tránh cấp phát quá lớn với `ESC[99999999C`.
+const MAX_CURSOR_COL: usize = u16::MAX as usize;
+
+/// Một phần tử của output terminal sau khi tách escape sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// Ký tự in được.
+    Print(char),
+    /// Ký tự điều khiển C0 (`\n`, `\r`, `\t`, backspace...).
+    Control(char),
+    /// CSI: tham số (ví dụ `"1;31"`) và ký tự kết thúc (ví dụ `'m'`).
+    Csi { params: &'a str, action: char },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Ground,
+    Escape,
+    /// `ESC` + byte trung gian (`ESC ( B`, `ESC # 8`...).
+    EscIntermediate,
+    /// CSI bắt đầu tại vị trí (byte) này.
+    Csi(usize),
+    /// OSC, DCS, SOS, PM, APC: bỏ tới BEL hoặc ST.
+    String,
+    StringEscape,
+}
+
+/// Tách `text` thành ký tự in được, ký tự điều khiển và CSI; OSC/DCS và các escape khác bị bỏ.
+pub fn tokenize(text: &str, mut emit: impl FnMut(Token<'_>)) {
+    let mut state = State::Ground;
+    for (i, c) in text.char_indices() {
+        state = match state {
+            State::Ground => match c {
+                '\x1b' => State::Escape,
+                '\x7f' | '\u{80}'..='\u{9f}' => State::Ground,
+                c if c < ' ' => {
+                    emit(Token::Control(c));
+                    State::Ground
+                }
+                c => {
+                    emit(Token::Print(c));
+                    State::Ground
+                }
+            },
+            State::StringEscape if c == '\\' => State::Ground,
+            // ESC khác trong chuỗi: chuỗi bị huỷ, bắt đầu escape mới
+            State::Escape | State::StringEscape => match c {
+                '[' => State::Csi(i + 1),
+                ']' | 'P' | 'X' | '^' | '_' => State::String,
+                '\x1b' => State::Escape,
+                ' '..='/' => State::EscIntermediate,
+                _ => State::Ground,
+            },
+            State::EscIntermediate => match c {
+                ' '..='/' => State::EscIntermediate,
+                '\x1b' => State::Escape,
+                _ => State::Ground,
+            },
+            State::Csi(start) => match c {
+                '@'..='~' => {
+                    emit(Token::Csi { params: &text[start..i], action: c });
+                    State::Ground
+                }
+                '\x1b' => State::Escape,
+                '\x18' | '\x1a' => State::Ground,
+                _ => State::Csi(start),
+            },
+            State::String => match c {
+                '\x07' => State::Ground,
+                '\x1b' => State::StringEscape,
+                _ => State::String,
+            },
+        };
+    }
+}
+
+/// Tham số thứ `index` của CSI; thiếu hoặc bằng 0 thì dùng `default`.
+#[must_use]
+pub fn csi_param(params: &str, index: usize, default: usize) -> usize {
+    params
+        .trim_start_matches(['?', '>', '<', '='])
+        .split(';')
+        .nth(index)
+        .and_then(|p| p.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(default)
+}
+
+/// Bảng màu 16 màu cơ bản (giá trị mặc định của xterm).
+const PALETTE: [u32; 16] = [
+    0x00_0000, 0xcd_0000, 0x00_cd00, 0xcd_cd00, 0x00_00ee, 0xcd_00cd, 0x00_cdcd, 0xe5_e5e5,
+    0x7f_7f7f, 0xff_0000, 0x00_ff00, 0xff_ff00, 0x5c_5cff, 0xff_00ff, 0x00_ffff, 0xff_ffff,
+];
+/// Màu chữ và nền mặc định của trang HTML xuất ra (dùng khi đảo màu).
+const DEFAULT_FG: u32 = 0xe5_e5e5;
+const DEFAULT_BG: u32 = 0x00_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// Màu trong bảng 256 màu.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    #[must_use]
+    pub fn rgb(self) -> u32 {
+        match self {
+            Self::Indexed(n @ 0..16) => PALETTE[usize::from(n)],
+            // Khối màu 6x6x6
+            Self::Indexed(n @ 16..232) => {
+                let level = |v: u8| if v == 0 { 0 } else { u32::from(v) * 40 + 55 };
+                let n = n - 16;
+                (level(n / 36) << 16) | (level(n / 6 % 6) << 8) | level(n % 6)
+            }
+            Self::Indexed(n) => {
+                let gray = u32::from(n - 232) * 10 + 8;
+                (gray << 16) | (gray << 8) | gray
+            }
+            Self::Rgb(r, g, b) => (u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b),
+        }
+    }
+}
+
+/// Thuộc tính hiển thị đặt bằng SGR (`ESC[...m`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub strikethrough: bool,
+}
+
+impl Style {
+    pub const PLAIN: Self = Self {
+        fg: None,
+        bg: None,
+        bold: false,
+        dim: false,
+        italic: false,
+        underline: false,
+        inverse: false,
+        strikethrough: false,
+    };
+
+    /// Áp dụng tham số SGR, gồm cả màu mở rộng `38;5;n`, `38;2;r;g;b` và dạng `38:2::r:g:b`.
+    pub fn apply_sgr(&mut self, params: &str) {
+        let parts: Vec<&str> = params.split(';').collect();
+        let num = |i: usize| parts.get(i).and_then(|p| p.parse::<u8>().ok()).unwrap_or(0);
+        let mut i = 0;
+        while i < parts.len() {
+            let code = parts[i];
+            if code.contains(':') {
+                let sub: Vec<u8> = code.split(':').map(|p| p.parse().unwrap_or(0)).collect();
+                match sub[0] {
+                    38 => self.fg = extended_color(&sub[1..]).or(self.fg),
+                    48 => self.bg = extended_color(&sub[1..]).or(self.bg),
+                    4 => self.underline = sub.get(1).is_some_and(|&s| s != 0),
+                    _ => {}
+                }
+                i += 1;
+                continue;
+            }
+            match code.parse::<u8>().unwrap_or(0) {
+                0 => *self = Self::PLAIN,
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                9 => self.strikethrough = true,
+                22 => (self.bold, self.dim) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                29 => self.strikethrough = false,
+                n @ 30..=37 => self.fg = Some(Color::Indexed(n - 30)),
+                39 => self.fg = None,
+                n @ 40..=47 => self.bg = Some(Color::Indexed(n - 40)),
+                49 => self.bg = None,
+                n @ 90..=97 => self.fg = Some(Color::Indexed(n - 90 + 8)),
+                n @ 100..=107 => self.bg = Some(Color::Indexed(n - 100 + 8)),
+                n @ (38 | 48) => {
+                    let (color, used) = match num(i + 1) {
+                        5 => (Some(Color::Indexed(num(i + 2))), 2),
+                        2 => (Some(Color::Rgb(num(i + 2), num(i + 3), num(i + 4))), 4),
+                        _ => (None, 0),
+                    };
+                    if n == 38 {
+                        self.fg = color.or(self.fg);
+                    } else {
+                        self.bg = color.or(self.bg);
+                    }
+                    i += used;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// Thuộc tính CSS inline; rỗng nếu là style mặc định.
+    #[must_use]
+    pub fn css(&self) -> String {
+        let (mut fg, mut bg) = (self.fg.map(Color::rgb), self.bg.map(Color::rgb));
+        if self.inverse {
+            (fg, bg) = (Some(bg.unwrap_or(DEFAULT_BG)), Some(fg.unwrap_or(DEFAULT_FG)));
+        }
+        let mut css = String::new();
+        if let Some(fg) = fg {
+            let _ = write!(css, "color:#{fg:06x};");
+        }
+        if let Some(bg) = bg {
+            let _ = write!(css, "background-color:#{bg:06x};");
+        }
+        if self.bold {
+            css.push_str("font-weight:bold;");
+        }
+        if self.dim {
+            css.push_str("opacity:0.5;");
+        }
+        if self.italic {
+            css.push_str("font-style:italic;");
+        }
+        match (self.underline, self.strikethrough) {
+            (true, true) => css.push_str("text-decoration:underline line-through;"),
+            (true, false) => css.push_str("text-decoration:underline;"),
+            (false, true) => css.push_str("text-decoration:line-through;"),
+            (false, false) => {}
+        }
+        css
+    }
+}
+
+/// `5;n` hoặc `2;r;g;b` (dạng có dấu `:` có thể kèm color space rỗng: `2::r:g:b`).
+fn extended_color(sub: &[u8]) -> Option<Color> {
+    match sub {
+        [5, n, ..] => Some(Color::Indexed(*n)),
+        [2, _, r, g, b, ..] | [2, r, g, b] => Some(Color::Rgb(*r, *g, *b)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub style: Style,
+}
+
+impl Cell {
+    const BLANK: Self = Self { c: ' ', style: Style::PLAIN };
+
+    /// Khoảng trắng không có nền: bỏ được ở cuối dòng.
+    const fn is_blank(&self) -> bool {
+        self.c == ' ' && self.style.bg.is_none() && !self.style.inverse
+    }
+}
+
+/// Dựng lại các dòng như terminal hiển thị: `\r` và backspace ghi đè,
+/// `ESC[K` xoá cuối dòng, di chuyển con trỏ ngang được áp dụng, SGR đổi style.
+#[derive(Debug, Default)]
+struct LineBuilder {
+    lines: Vec<Vec<Cell>>,
+    line: Vec<Cell>,
+    col: usize,
+    style: Style,
+}
+
+impl LineBuilder {
+    fn build(text: &str) -> Vec<Vec<Cell>> {
+        let mut builder = Self::default();
+        tokenize(text, |token| match token {
+            Token::Print(c) => builder.print(c),
+            Token::Control(c) => builder.control(c),
+            Token::Csi { params, action } => builder.csi(params, action),
+        });
+        builder.finish()
+    }
+
+    fn print(&mut self, c: char) {
+        let cell = Cell { c, style: self.style };
+        if self.col < self.line.len() {
+            self.line[self.col] = cell;
+        } else {
+            self.line.resize(self.col, Cell::BLANK);
+            self.line.push(cell);
+        }
+        self.col += 1;
+    }
+
+    fn control(&mut self, c: char) {
+        match c {
+            '\n' => self.newline(),
+            '\r' => self.col = 0,
+            '\x08' => self.col = self.col.saturating_sub(1),
+            '\t' => self.col = ((self.col / 8 + 1) * 8).min(MAX_CURSOR_COL),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, params: &str, action: char) {
+        match action {
+            'm' => self.style.apply_sgr(params),
+            // CUF / CUB / CHA
+            'C' => self.col = (self.col + csi_param(params, 0, 1)).min(MAX_CURSOR_COL),
+            'D' => self.col = self.col.saturating_sub(csi_param(params, 0, 1)),
+            'G' => self.col = (csi_param(params, 0, 1) - 1).min(MAX_CURSOR_COL),
+            // EL: 0 = tới cuối dòng, 1 = từ đầu dòng, 2 = cả dòng
+            'K' => match params {
+                "" | "0" => self.line.truncate(self.col),
+                "1" => {
+                    let end = (self.col + 1).min(self.line.len());
+                    self.line[..end].fill(Cell::BLANK);
+                }
+                "2" => self.line.clear(),
+                _ => {}
+            },
+            // ECH: thay n ký tự bằng khoảng trắng
+            'X' => {
+                let end = (self.col + csi_param(params, 0, 1)).min(self.line.len());
+                if self.col < end {
+                    self.line[self.col..end].fill(Cell::BLANK);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn newline(&mut self) {
+        let mut line = std::mem::take(&mut self.line);
+        while line.last().is_some_and(Cell::is_blank) {
+            line.pop();
+        }
+        self.lines.push(line);
+        self.col = 0;
+    }
+
+    fn finish(mut self) -> Vec<Vec<Cell>> {
+        if !self.line.is_empty() {
+            self.newline();
+        }
+        self.lines
+    }
+}
+
+/// Bỏ escape sequence khỏi output thô và tách thành dòng văn bản (đã bỏ khoảng trắng cuối dòng).
+#[must_use]
+pub fn text_lines(data: &[u8]) -> Vec<String> {
+    LineBuilder::build(&String::from_utf8_lossy(data))
+        .into_iter()
+        .map(|line| line.iter().map(|cell| cell.c).collect::<String>().trim_end().to_string())
+        .collect()
+}
+
+/// Escape `&`, `<`, `>`, `"` cho HTML.
+#[must_use]
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Chuyển output thô thành HTML (nội dung của `<pre>`): văn bản đã bỏ escape sequence,
+/// màu và thuộc tính SGR giữ lại bằng `<span style>`.
+#[must_use]
+pub fn html_lines(data: &[u8]) -> String {
+    let mut html = String::new();
+    for line in LineBuilder::build(&String::from_utf8_lossy(data)) {
+        for run in line.chunk_by(|a, b| a.style == b.style) {
+            let text: String = run.iter().map(|cell| cell.c).collect();
+            let css = run[0].style.css();
+            if css.is_empty() {
+                html.push_str(&escape_html(&text));
+            } else {
+                let _ = write!(html, "<span style=\"{css}\">{}</span>", escape_html(&text));
+            }
+        }
+        html.push('\n');
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_sequences() {
+        let mut tokens = Vec::new();
+        tokenize("a\x1b[1;31mb\x1b]0;title\x07\x1bP1$r\x1b\\\x1b(Bc\x1b]7;x\x1b\\\n", |t| tokens.push(format!("{t:?}")));
+        assert_eq!(
+            tokens,
+            [
+                "Print('a')",
+                "Csi { params: \"1;31\", action: 'm' }",
+                "Print('b')",
+                "Print('c')",
+                "Control('\\n')",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_lines_strips_escapes() {
+        let output = b"\x1b]0;user@host: ~\x07\x1b[01;32muser@host\x1b[00m:\x1b[01;34m~\x1b[00m$ ls\r\n\x1b[0m\x1b[01;34mdocs\x1b[0m  src\r\n";
+        assert_eq!(text_lines(output), ["user@host:~$ ls", "docs  src"]);
+        assert!(text_lines(b"").is_empty());
+        assert_eq!(text_lines(b"no newline"), ["no newline"]);
+        assert_eq!(text_lines(b"a\n\nb\n"), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn test_text_lines_applies_overwrites() {
+        // Thanh tiến trình ghi đè bằng \r, backspace, xoá cuối dòng
+        assert_eq!(text_lines(b" 10%\r 50%\r100% done\n"), ["100% done"]);
+        assert_eq!(text_lines(b"abc\x08\x08X\n"), ["aXc"]);
+        assert_eq!(text_lines(b"long line\r\x1b[Kshort\n"), ["short"]);
+        assert_eq!(text_lines(b"hello\x1b[3D\x1b[K\n"), ["he"]);
+        assert_eq!(text_lines(b"a\tb\x1b[3Cc\x1b[1Gd\n"), ["d       b   c"]);
+        assert_eq!(text_lines(b"abcdef\x1b[3G\x1b[2X\n"), ["ab  ef"]);
+        assert_eq!(text_lines(b"x\x1b[99999999Cy\n")[0].chars().count(), MAX_CURSOR_COL + 1);
+    }
+
+    #[test]
+    fn test_csi_param() {
+        assert_eq!(csi_param("", 0, 1), 1);
+        assert_eq!(csi_param("5", 0, 1), 5);
+        assert_eq!(csi_param("0", 0, 1), 1);
+        assert_eq!(csi_param("1;31", 1, 0), 31);
+        assert_eq!(csi_param("?25", 0, 0), 25);
+    }
+
+    #[test]
+    fn test_sgr_styles() {
+        let mut style = Style::default();
+        style.apply_sgr("1;31;44");
+        assert_eq!(style, Style { fg: Some(Color::Indexed(1)), bg: Some(Color::Indexed(4)), bold: true, ..Style::PLAIN });
+        style.apply_sgr("22;39;92");
+        assert_eq!(style, Style { fg: Some(Color::Indexed(10)), bg: Some(Color::Indexed(4)), ..Style::PLAIN });
+        style.apply_sgr("38;5;208;48;2;1;2;3;4");
+        assert_eq!((style.fg, style.bg, style.underline), (Some(Color::Indexed(208)), Some(Color::Rgb(1, 2, 3)), true));
+        style.apply_sgr("38:2::10:20:30");
+        assert_eq!(style.fg, Some(Color::Rgb(10, 20, 30)));
+        style.apply_sgr("");
+        assert_eq!(style, Style::PLAIN);
+
+        assert_eq!(Color::Indexed(1).rgb(), 0xcd_0000);
+        assert_eq!(Color::Indexed(208).rgb(), 0xff_8700);
+        assert_eq!(Color::Indexed(244).rgb(), 0x80_8080);
+        assert_eq!(Color::Rgb(1, 2, 3).rgb(), 0x01_0203);
+    }
+
+    #[test]
+    fn test_html_lines() {
+        let html = html_lines(b"\x1b[1;31merror\x1b[0m: <a> & \"b\"\r\n\x1b[7m  \x1b[m\r\nplain\x1b[42m   \x1b[0m   \n");
+        assert_eq!(
+            html,
+            "<span style=\"color:#cd0000;font-weight:bold;\">error</span>: &lt;a&gt; &amp; &quot;b&quot;\n\
+             <span style=\"color:#000000;background-color:#e5e5e5;\">  </span>\n\
+             plain<span style=\"background-color:#00cd00;\">   </span>\n"
+        );
+        // Ghi đè giữ style của ký tự mới
+        assert_eq!(html_lines(b"\x1b[32mab\x1b[0m\rX\n"), "X<span style=\"color:#00cd00;\">b</span>\n");
+    }
+}
@@ -1,42 +1,519 @@
-use axum::extract::{Json, State};
-use axum::response::IntoResponse;
+use axum::extract::{Extension, Json, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use std::sync::Arc;
-use crate::session::SessionInfo;
+use crate::auth::CurrentUser;
+use crate::pty_manager::{ProcessEntry, ResourceUsage, SessionSignal, SignalTarget};
+use crate::session::{MetadataUpdate, Session, SessionError, SessionInfo, SessionOptions};
+use crate::db::TemplateWrite;
+use crate::templates::SessionTemplate;
 use crate::AppState;
 use serde::Deserialize;
 use axum::response::sse::{Event, Sse};
 use futures_util::stream::Stream;
 use std::convert::Infallible;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct CreateSessionRequest {
-    pub id: String,
+    /// ID do client chọn; bỏ trống để server tự sinh.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Dung lượng lịch sử output riêng cho session (byte), bị giới hạn bởi `SESSION_HISTORY_BYTES`.
+    #[serde(default)]
+    pub history_size: Option<usize>,
+    /// Dung lượng lịch sử ghi ra đĩa (byte), bị giới hạn bởi `SESSION_SPILL_BYTES`; `0` để tắt.
+    #[serde(default)]
+    pub spill_size: Option<u64>,
+    /// Tên hiển thị.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Tên host trong keystore SSH; nếu có, session là kết nối SSH thay vì shell cục bộ.
+    #[serde(default)]
+    pub ssh: Option<String>,
+    /// Tên template của user (command, cwd, env, kích thước, recording, idle timeout).
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+impl IntoResponse for SessionError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::TooManySessions { .. }
+            | Self::TooManySessionsForUser { .. }
+            | Self::TooManyClients { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::NotFound { .. } | Self::ProcessNotFound { .. } => StatusCode::NOT_FOUND,
+            Self::AlreadyExists { .. }
+            | Self::SignalFailed { .. }
+            | Self::ProcessExited
+            | Self::CloneUnsupported { .. } => StatusCode::CONFLICT,
+            Self::Forbidden | Self::UnixUserNotMapped => StatusCode::FORBIDDEN,
+            Self::SpawnFailed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidId { .. }
+            | Self::InvalidMetadata { .. }
+            | Self::UnknownSshHost { .. }
+            | Self::UnknownTemplate { .. }
+            | Self::InvalidTemplate { .. }
+            | Self::RecordingUnavailable => StatusCode::BAD_REQUEST,
+            Self::ConnectFailed { .. } => StatusCode::BAD_GATEWAY,
+        };
+        (status, Json(serde_json::json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SignalRequest {
+    pub signal: SessionSignal,
+    #[serde(default)]
+    pub target: SignalTarget,
+}
+
+#[derive(Deserialize)]
+pub struct ProcessSignalRequest {
+    pub signal: SessionSignal,
+}
+
+#[derive(serde::Serialize)]
+pub struct SignalResponse {
+    pub signal: SessionSignal,
+    pub target: SignalTarget,
+    /// Các process group đã nhận tín hiệu.
+    pub process_groups: Vec<u32>,
+}
+
+/// Chỉ owner của session hoặc admin được điều khiển tiến trình trong session.
+fn authorize(user: &crate::db::User, session: &Session) -> Result<(), SessionError> {
+    if user.role == "admin" || session.owner == Some(user.id) {
+        Ok(())
+    } else {
+        Err(SessionError::Forbidden)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UnixUserRequest {
+    /// Tên tài khoản Unix; `null` để bỏ map.
+    pub unix_user: Option<String>,
+}
+
+/// Map user với tài khoản Unix dùng để chạy shell (chỉ admin).
+pub async fn set_unix_user(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(user_id): axum::extract::Path<i64>,
+    Json(payload): Json<UnixUserRequest>,
+) -> Response {
+    let error = |status: StatusCode, message: String| (status, Json(serde_json::json!({ "error": message }))).into_response();
+    if user.role != "admin" {
+        return error(StatusCode::FORBIDDEN, "only admins can map unix accounts".to_string());
+    }
+    #[cfg(unix)]
+    if let Some(name) = &payload.unix_user
+        && let Err(e) = crate::sandbox::UnixAccount::lookup(name)
+    {
+        return error(StatusCode::BAD_REQUEST, e.to_string());
+    }
+    match state.db.set_unix_user(user_id, payload.unix_user.as_deref()).await {
+        Ok(true) => {
+            tracing::info!(user_id, unix_user = ?payload.unix_user, "unix account mapping updated");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => error(StatusCode::NOT_FOUND, format!("user {user_id} not found")),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to update unix account mapping");
+            error(StatusCode::INTERNAL_SERVER_ERROR, "database error".to_string())
+        }
+    }
+}
+
+fn json_error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
+pub async fn list_templates(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+) -> Response {
+    match state.db.list_templates(user.id).await {
+        Ok(templates) => Json(templates).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to list session templates");
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "database error")
+        }
+    }
+}
+
+pub async fn get_template(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> Response {
+    match state.db.get_template(user.id, &name).await {
+        Ok(Some(template)) => Json(template).into_response(),
+        Ok(None) => json_error(StatusCode::NOT_FOUND, format!("template '{name}' not found")),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to load session template");
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "database error")
+        }
+    }
+}
+
+/// Tạo template mới cho user hiện tại.
+pub async fn create_template(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    Json(template): Json<SessionTemplate>,
+) -> Response {
+    if let Err(e) = template.validate() {
+        return e.into_response();
+    }
+    match state.db.create_template(user.id, &template).await {
+        Ok(TemplateWrite::Saved) => {
+            tracing::info!(template = %template.name, "session template created");
+            (StatusCode::CREATED, Json(template)).into_response()
+        }
+        Ok(_) => json_error(StatusCode::CONFLICT, format!("template '{}' already exists", template.name)),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to create session template");
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "database error")
+        }
+    }
+}
+
+/// Thay toàn bộ nội dung template (đổi tên bằng `name` trong body).
+pub async fn update_template(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    Json(template): Json<SessionTemplate>,
+) -> Response {
+    if let Err(e) = template.validate() {
+        return e.into_response();
+    }
+    match state.db.update_template(user.id, &name, &template).await {
+        Ok(TemplateWrite::Saved) => {
+            tracing::info!(template = %name, "session template updated");
+            Json(template).into_response()
+        }
+        Ok(TemplateWrite::NotFound) => json_error(StatusCode::NOT_FOUND, format!("template '{name}' not found")),
+        Ok(TemplateWrite::NameTaken) => json_error(StatusCode::CONFLICT, format!("template '{}' already exists", template.name)),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to update session template");
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "database error")
+        }
+    }
+}
+
+pub async fn delete_template(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> Response {
+    match state.db.delete_template(user.id, &name).await {
+        Ok(true) => {
+            tracing::info!(template = %name, "session template deleted");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => json_error(StatusCode::NOT_FOUND, format!("template '{name}' not found")),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to delete session template");
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "database error")
+        }
+    }
+}
+
+/// Các host SSH mà user được phép kết nối (không gồm khóa, mật khẩu).
+pub async fn list_ssh_hosts(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+) -> Response {
+    let Some(dir) = &state.config.ssh_keystore else {
+        return Json(Vec::<crate::ssh::SshHost>::new()).into_response();
+    };
+    match crate::ssh::Keystore::new(dir).hosts() {
+        Ok(hosts) => Json(hosts.into_iter().filter(|h| h.allows(&user.username)).collect::<Vec<_>>()).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to read ssh keystore");
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({ "error": "failed to read ssh keystore" }))).into_response()
+        }
+    }
 }
 
 pub async fn list_sessions(
     State(state): State<Arc<AppState>>,
 ) -> Json<Vec<SessionInfo>> {
-    println!("API: Listing sessions");
+    tracing::debug!("listing sessions");
     Json(state.registry.list_sessions())
 }
 
 pub async fn create_session(
     State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
     Json(payload): Json<CreateSessionRequest>,
 ) -> impl IntoResponse {
-    println!("API: Creating session with ID: {}", payload.id);
-    let _session = state.registry.create_session(payload.id.clone());
-    let _ = state.tx.send(crate::GlobalEvent::SessionCreated(payload.id));
-    println!("API: Session created successfully.");
-    Json("Created").into_response()
+    let id = payload.id.unwrap_or_else(crate::session::generate_id);
+    tracing::Span::current().record("session_id", id.as_str());
+    let mut options = SessionOptions::new(id.clone()).owner(user.id).username(user.username.clone());
+    if let Some(name) = &payload.template {
+        if payload.ssh.is_some() {
+            return SessionError::InvalidTemplate { reason: "templates cannot be combined with ssh" }.into_response();
+        }
+        match state.db.get_template(user.id, name).await {
+            Ok(Some(template)) => options = template.apply(options),
+            Ok(None) => return SessionError::UnknownTemplate { name: name.clone() }.into_response(),
+            Err(e) => {
+                tracing::error!(error = %e, "failed to load session template");
+                return json_error(StatusCode::INTERNAL_SERVER_ERROR, "database error");
+            }
+        }
+    }
+    if let Some(host) = &payload.ssh {
+        let Some(dir) = &state.config.ssh_keystore else {
+            return SessionError::UnknownSshHost { name: host.clone() }.into_response();
+        };
+        match crate::ssh::open(&crate::ssh::Keystore::new(dir), host, &user.username).await {
+            Ok(connection) => options = options.backend(Arc::new(connection)),
+            Err(e) => return e.into_response(),
+        }
+    }
+    if let Some(size) = payload.history_size {
+        options = options.history_size(size);
+    }
+    if let Some(size) = payload.spill_size {
+        options = options.spill_size(size);
+    }
+    if let Some(name) = payload.name {
+        let check = MetadataUpdate { name: Some(name.clone()), ..MetadataUpdate::default() };
+        if let Err(e) = check.validate() {
+            return e.into_response();
+        }
+        options = options.name(name);
+    }
+    spawn_session(&state, &user, options)
+}
+
+/// Tạo session từ `options` cho `user` và báo cho các dashboard.
+fn spawn_session(state: &AppState, user: &crate::db::User, mut options: SessionOptions) -> Response {
+    if options.backend.is_none() && state.config.spawn_as_unix_user {
+        // Không bao giờ rơi về chạy shell dưới quyền của server
+        let Some(unix_user) = &user.unix_user else {
+            tracing::warn!(user_id = user.id, "session creation refused: no unix account mapped");
+            return SessionError::UnixUserNotMapped.into_response();
+        };
+        options = options.unix_user(unix_user.clone());
+    }
+    let id = options.id.clone();
+    let session = match state.registry.create_session(options) {
+        Ok(session) => session,
+        Err(e) => {
+            tracing::warn!(error = %e, "session creation rejected");
+            return e.into_response();
+        }
+    };
+    let _ = state.tx.send(crate::GlobalEvent::SessionCreated(id));
+    tracing::info!("session created");
+    (StatusCode::CREATED, Json(session.info())).into_response()
+}
+
+#[derive(Deserialize, Default)]
+pub struct CloneSessionRequest {
+    /// ID của session mới; bỏ trống để server tự sinh.
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Tạo session mới cùng lệnh, môi trường và thư mục hiện tại với session `id`.
+pub async fn clone_session(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    payload: Option<Json<CloneSessionRequest>>,
+) -> Response {
+    tracing::Span::current().record("session_id", id.as_str());
+    let Some(source) = state.registry.get_session(&id) else {
+        return SessionError::NotFound { id }.into_response();
+    };
+    // Clone lộ env và thư mục của session nguồn
+    if let Err(e) = authorize(&user, &source) {
+        return e.into_response();
+    }
+    let backend = source.backend.kind();
+    if backend != "pty" {
+        return SessionError::CloneUnsupported { backend }.into_response();
+    }
+    let Json(request) = payload.unwrap_or_default();
+    let new_id = request.id.unwrap_or_else(crate::session::generate_id);
+    let mut options = source.clone_options(new_id).owner(user.id).username(user.username.clone());
+    if let Some(name) = request.name {
+        let check = MetadataUpdate { name: Some(name.clone()), ..MetadataUpdate::default() };
+        if let Err(e) = check.validate() {
+            return e.into_response();
+        }
+        options = options.name(name);
+    }
+    tracing::info!(clone = %options.id, cwd = ?options.cwd, "cloning session");
+    spawn_session(&state, &user, options)
+}
+
+pub async fn get_session(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<SessionInfo>, SessionError> {
+    tracing::Span::current().record("session_id", id.as_str());
+    state
+        .registry
+        .get_session(&id)
+        .map(|s| Json(s.info()))
+        .ok_or(SessionError::NotFound { id })
+}
+
+pub async fn update_session(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(update): Json<MetadataUpdate>,
+) -> Result<Json<SessionInfo>, SessionError> {
+    tracing::Span::current().record("session_id", id.as_str());
+    let session = state.registry.get_session(&id).ok_or(SessionError::NotFound { id })?;
+    let info = session.update_metadata(update)?;
+    let _ = state.tx.send(crate::GlobalEvent::SessionUpdated(Box::new(info.clone())));
+    tracing::info!("session metadata updated");
+    Ok(Json(info))
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Hiểu `q` là regex thay vì chuỗi thường.
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub ignore_case: bool,
+    /// Số dòng trước/sau mỗi kết quả (tối đa 10).
+    pub context: Option<usize>,
+    /// Số kết quả tối đa (tối đa 1000).
+    pub limit: Option<usize>,
+}
+
+/// Tìm trong lịch sử output của session (đã bỏ escape sequence). Chỉ owner hoặc admin.
+pub async fn search_session(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(query): Query<SearchQuery>,
+) -> Response {
+    use crate::scrollback::{self, MAX_SEARCH_CONTEXT, MAX_SEARCH_LIMIT};
+    tracing::Span::current().record("session_id", id.as_str());
+    let Some(session) = state.registry.get_session(&id) else {
+        return SessionError::NotFound { id }.into_response();
+    };
+    // Output có thể chứa dữ liệu nhạy cảm của owner
+    if let Err(e) = authorize(&user, &session) {
+        return e.into_response();
+    }
+    if query.q.is_empty() {
+        return json_error(StatusCode::BAD_REQUEST, "q must not be empty");
+    }
+    let re = match scrollback::matcher(&query.q, query.regex, query.ignore_case) {
+        Ok(re) => re,
+        Err(e) => return json_error(StatusCode::BAD_REQUEST, format!("invalid regex: {e}")),
+    };
+    let context = query.context.unwrap_or(scrollback::DEFAULT_SEARCH_CONTEXT).min(MAX_SEARCH_CONTEXT);
+    let limit = query.limit.unwrap_or(scrollback::DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+    let history = session.history.lock().unwrap().full();
+    let lines = crate::ansi::text_lines(&history);
+    Json(scrollback::search(&lines, &re, context, limit)).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct ScrollbackQuery {
+    #[serde(default)]
+    pub format: crate::scrollback::ScrollbackFormat,
+}
+
+/// Lịch sử output của session dạng `raw`, `text` hoặc `html`. Chỉ owner hoặc admin.
+pub async fn session_scrollback(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(query): Query<ScrollbackQuery>,
+) -> Response {
+    tracing::Span::current().record("session_id", id.as_str());
+    let Some(session) = state.registry.get_session(&id) else {
+        return SessionError::NotFound { id }.into_response();
+    };
+    if let Err(e) = authorize(&user, &session) {
+        return e.into_response();
+    }
+    let history = session.history.lock().unwrap().full();
+    let title = session.metadata.lock().unwrap().name.clone().unwrap_or(id);
+    let body = query.format.render(history, &title);
+    ([(axum::http::header::CONTENT_TYPE, query.format.content_type())], body).into_response()
+}
+
+pub async fn session_stats(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<ResourceUsage>, SessionError> {
+    tracing::Span::current().record("session_id", id.as_str());
+    let session = state.registry.get_session(&id).ok_or(SessionError::NotFound { id })?;
+    session.backend.sample_usage().map(Json).ok_or(SessionError::ProcessExited)
+}
+
+pub async fn list_processes(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<Vec<ProcessEntry>>, SessionError> {
+    tracing::Span::current().record("session_id", id.as_str());
+    let session = state.registry.get_session(&id).ok_or(SessionError::NotFound { id })?;
+    Ok(Json(session.backend.process_tree()))
+}
+
+pub async fn signal_process(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path((id, pid)): axum::extract::Path<(String, u32)>,
+    Json(req): Json<ProcessSignalRequest>,
+) -> Result<StatusCode, SessionError> {
+    tracing::Span::current().record("session_id", id.as_str());
+    let session = state.registry.get_session(&id).ok_or(SessionError::NotFound { id })?;
+    authorize(&user, &session)?;
+    if !session.backend.process_tree().iter().any(|p| p.pid == pid) {
+        return Err(SessionError::ProcessNotFound { pid });
+    }
+    session
+        .backend
+        .signal_pid(pid, req.signal)
+        .map_err(|e| SessionError::SignalFailed { reason: e.to_string() })?;
+    tracing::info!(user = %user.username, pid, signal = ?req.signal, "signal delivered to process");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn signal_session(
+    State(state): State<Arc<AppState>>,
+    Extension(CurrentUser(user)): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(req): Json<SignalRequest>,
+) -> Result<Json<SignalResponse>, SessionError> {
+    tracing::Span::current().record("session_id", id.as_str());
+    let session = state.registry.get_session(&id).ok_or(SessionError::NotFound { id })?;
+    authorize(&user, &session)?;
+    let process_groups = session
+        .backend
+        .signal(req.signal, req.target)
+        .map_err(|e| SessionError::SignalFailed { reason: e.to_string() })?;
+    tracing::info!(user = %user.username, signal = ?req.signal, target = ?req.target, ?process_groups, "signal delivered");
+    Ok(Json(SignalResponse { signal: req.signal, target: req.target, process_groups }))
 }
 
 pub async fn delete_session(
     State(state): State<Arc<AppState>>,
     axum::extract::Path(id): axum::extract::Path<String>,
 ) -> impl IntoResponse {
+    tracing::Span::current().record("session_id", id.as_str());
     state.registry.remove_session(&id);
     let _ = state.tx.send(crate::GlobalEvent::SessionDeleted(id));
+    tracing::info!("session deleted");
     axum::http::StatusCode::OK.into_response()
 }
 
@@ -44,11 +521,21 @@ pub async fn events_handler(
     State(state): State<Arc<AppState>>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     let mut rx = state.tx.subscribe();
+    tracing::debug!("SSE subscriber connected");
 
     let stream = async_stream::stream! {
-        while let Ok(msg) = rx.recv().await {
-            if let Ok(data) = serde_json::to_string(&msg) {
-                yield Ok(Event::default().data(data));
+        let _subscriber = crate::metrics::GaugeGuard::new(crate::metrics::SSE_SUBSCRIBERS.clone());
+        loop {
+            match rx.recv().await {
+                Ok(msg) => {
+                    if let Ok(data) = serde_json::to_string(&msg) {
+                        yield Ok(Event::default().data(data));
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(skipped = n, "SSE subscriber lagged");
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
             }
         }
     };
@@ -62,13 +549,27 @@ mod tests {
     use axum::extract::Path;
     use crate::session::SessionRegistry;
     use crate::db::Db;
+    use crate::config::{Config, SessionLimits};
+    use crate::db::User;
     use tokio::sync::broadcast;
+    use crate::scrollback::ScrollbackFormat;
 
     async fn setup() -> Arc<AppState> {
         let (tx, _) = broadcast::channel(10);
         let registry = Arc::new(SessionRegistry::new(tx.clone()));
         let db = Db::new("sqlite::memory:").await.unwrap();
-        Arc::new(AppState { registry, tx, db })
+        Arc::new(AppState { registry, tx, db, config: Config::default() })
+    }
+
+    fn user(id: i64) -> Extension<CurrentUser> {
+        Extension(CurrentUser(User {
+            id,
+            username: format!("user{id}"),
+            password_hash: String::new(),
+            role: "member".to_string(),
+            must_change_password: false,
+            unix_user: None,
+        }))
     }
 
     #[tokio::test]
@@ -76,8 +577,8 @@ mod tests {
         let state = setup().await;
         
         // Create
-        let req = Json(CreateSessionRequest { id: "test-id".to_string() });
-        create_session(State(state.clone()), req).await;
+        let req = Json(CreateSessionRequest { id: Some("test-id".to_string()), ..Default::default() });
+        create_session(State(state.clone()), user(1), req).await;
         
         // List
         let Json(sessions) = list_sessions(State(state.clone())).await;
@@ -85,14 +586,560 @@ mod tests {
         assert_eq!(sessions[0].id, "test-id");
     }
 
+    async fn json_body(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_session_conflict_and_validation() {
+        let state = setup().await;
+        let req = |id: &str| Json(CreateSessionRequest { id: Some(id.to_string()), ..Default::default() });
+
+        let response = create_session(State(state.clone()), user(1), req("dup")).await.into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(json_body(response).await["id"], "dup");
+        let original = state.registry.get_session("dup").unwrap();
+
+        // Trùng ID: 409 và session đang chạy không bị thay thế
+        let response = create_session(State(state.clone()), user(1), req("dup")).await.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(json_body(response).await["error"].as_str().unwrap().contains("already exists"));
+        assert!(Arc::ptr_eq(&state.registry.get_session("dup").unwrap().backend, &original.backend));
+
+        for bad in ["", "has space", "../etc", &"x".repeat(65)] {
+            let response = create_session(State(state.clone()), user(1), req(bad)).await.into_response();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "id {bad:?} should be rejected");
+        }
+        assert_eq!(state.registry.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_session_generates_id() {
+        let state = setup().await;
+        let response = create_session(State(state.clone()), user(1), Json(CreateSessionRequest::default()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let json = json_body(response).await;
+        let id = json["id"].as_str().unwrap();
+        assert!(crate::session::validate_id(id).is_ok());
+        assert!(state.registry.get_session(id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_session_returns_metadata() {
+        let state = setup().await;
+        let req = Json(CreateSessionRequest {
+            id: Some("info".to_string()),
+            name: Some("Build box".to_string()),
+            ..Default::default()
+        });
+        create_session(State(state.clone()), user(7), req).await;
+
+        let Json(info) = get_session(State(state.clone()), Path("info".to_string())).await.unwrap();
+        assert_eq!(info.id, "info");
+        assert_eq!(info.name, "Build box");
+        assert_eq!(info.owner, Some(7));
+        assert!(!info.command.is_empty());
+        assert!(info.pid.is_some());
+        assert_eq!(info.size, Some(crate::session::TermSize { rows: 24, cols: 80 }));
+        assert_eq!(info.clients, 0);
+        assert!(info.created_at > 0 && info.last_activity >= info.created_at);
+        assert_eq!(info.exit_status, None);
+
+        let response = get_session(State(state.clone()), Path("missing".to_string())).await.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_update_session_metadata() {
+        let state = setup().await;
+        let mut events = state.tx.subscribe();
+        state.registry.create_session("meta".to_string()).unwrap();
+
+        let update = MetadataUpdate {
+            name: Some("Deploy".to_string()),
+            tags: Some(vec!["prod".to_string(), " ops ".to_string()]),
+            description: Some("release shell".to_string()),
+        };
+        let Json(info) = update_session(State(state.clone()), Path("meta".to_string()), Json(update)).await.unwrap();
+        assert_eq!(info.name, "Deploy");
+        assert_eq!(info.tags, vec!["prod", "ops"]);
+        assert_eq!(info.description.as_deref(), Some("release shell"));
+
+        match events.recv().await.unwrap() {
+            crate::GlobalEvent::SessionUpdated(updated) => assert_eq!(updated.name, "Deploy"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        // Trường bỏ trống giữ nguyên; chuỗi rỗng đưa về mặc định
+        let update = MetadataUpdate { name: Some(String::new()), ..MetadataUpdate::default() };
+        let Json(info) = update_session(State(state.clone()), Path("meta".to_string()), Json(update)).await.unwrap();
+        assert_eq!(info.name, "meta");
+        assert_eq!(info.tags, vec!["prod", "ops"]);
+
+        let update = MetadataUpdate { tags: Some(vec!["x".repeat(33)]), ..MetadataUpdate::default() };
+        let response = update_session(State(state.clone()), Path("meta".to_string()), Json(update)).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = update_session(State(state.clone()), Path("missing".to_string()), Json(MetadataUpdate::default()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_signal_session() {
+        let state = setup().await;
+        let req = Json(CreateSessionRequest { id: Some("sig".to_string()), ..Default::default() });
+        create_session(State(state.clone()), user(1), req).await;
+        let session = state.registry.get_session("sig").unwrap();
+        let signal = |body: &str| Json(serde_json::from_str::<SignalRequest>(body).unwrap());
+
+        // User khác không được gửi tín hiệu
+        let response = signal_session(State(state.clone()), user(2), Path("sig".to_string()), signal(r#"{"signal": "SIGINT"}"#))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let wait_busy = |busy: bool| {
+            let session = session.clone();
+            async move {
+                for _ in 0..100 {
+                    if session.backend.foreground().is_some_and(|f| f.busy == busy) {
+                        return;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                }
+                panic!("foreground busy state never became {busy}");
+            }
+        };
+
+        // SIGINT tới foreground chỉ dừng lệnh đang chạy, shell vẫn sống
+        wait_busy(false).await;
+        session.backend.write(b"sleep 30\n").unwrap();
+        wait_busy(true).await;
+        let Json(resp) = signal_session(State(state.clone()), user(1), Path("sig".to_string()), signal(r#"{"signal": "INT"}"#))
+            .await
+            .unwrap();
+        assert_eq!(resp.target, SignalTarget::Foreground);
+        assert_ne!(resp.process_groups, vec![session.backend.pid().unwrap()]);
+        wait_busy(false).await;
+        assert_eq!(session.backend.exit_status(), None);
+
+        // Admin được phép; SIGKILL cả session kết thúc shell
+        let admin = Extension(CurrentUser(User { role: "admin".to_string(), ..user(99).0 .0 }));
+        let body = signal(r#"{"signal": "SIGKILL", "target": "session"}"#);
+        let Json(resp) = signal_session(State(state.clone()), admin, Path("sig".to_string()), body).await.unwrap();
+        assert!(resp.process_groups.contains(&session.backend.pid().unwrap()));
+        for _ in 0..100 {
+            if session.backend.exit_status().is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(session.backend.exit_status().is_some());
+
+        let response = signal_session(State(state.clone()), user(1), Path("missing".to_string()), signal(r#"{"signal": "SIGTERM"}"#))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(serde_json::from_str::<SignalRequest>(r#"{"signal": "SIGSEGV"}"#).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_session_stats() {
+        let state = setup().await;
+        let session = state.registry.create_session("stats".to_string()).unwrap();
+
+        let Json(usage) = session_stats(State(state.clone()), Path("stats".to_string())).await.unwrap();
+        assert!(usage.processes >= 1);
+        assert!(usage.rss_bytes > 0);
+        assert!(usage.open_fds > 0);
+        assert!(usage.cpu_percent >= 0.0);
+
+        // Tiến trình con cũng được tính
+        session.backend.write(b"sleep 30 &\n").unwrap();
+        let mut processes = usage.processes;
+        for _ in 0..100 {
+            processes = session.backend.sample_usage().unwrap().processes;
+            if processes > usage.processes {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(processes > usage.processes);
+
+        state.registry.terminate_session("stats");
+        let response = session_stats(State(state.clone()), Path("stats".to_string())).await.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_list_and_signal_processes() {
+        let state = setup().await;
+        let req = Json(CreateSessionRequest { id: Some("tree".to_string()), ..Default::default() });
+        create_session(State(state.clone()), user(1), req).await;
+        let session = state.registry.get_session("tree").unwrap();
+        let shell = session.backend.pid().unwrap();
+
+        session.backend.write(b"sleep 31 &\n").unwrap();
+        let mut sleep = None;
+        for _ in 0..100 {
+            let Json(tree) = list_processes(State(state.clone()), Path("tree".to_string())).await.unwrap();
+            assert_eq!(tree[0].pid, shell);
+            sleep = tree.into_iter().find(|p| p.command == "sleep 31");
+            if sleep.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        let sleep = sleep.expect("background sleep should appear in the process tree");
+        assert_eq!(sleep.ppid, shell);
+        assert!(sleep.started_at > 0);
+
+        let signal = || Json(ProcessSignalRequest { signal: SessionSignal::Term });
+        let path = |pid: u32| Path(("tree".to_string(), pid));
+
+        // PID ngoài cây tiến trình của session bị từ chối
+        let response = signal_process(State(state.clone()), user(1), path(std::process::id()), signal()).await.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = signal_process(State(state.clone()), user(2), path(sleep.pid), signal()).await.into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let status = signal_process(State(state.clone()), user(1), path(sleep.pid), signal()).await.unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        for _ in 0..100 {
+            if !session.backend.process_tree().iter().any(|p| p.pid == sleep.pid && p.state != 'Z') {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(!session.backend.process_tree().iter().any(|p| p.pid == sleep.pid && p.state != 'Z'));
+        assert!(session.backend.signal_pid(1, SessionSignal::Kill).is_err());
+    }
+
     #[tokio::test]
     async fn test_delete_session() {
         let state = setup().await;
-        let _ = state.registry.create_session("delete-me".to_string());
+        state.registry.create_session("delete-me".to_string()).unwrap();
         
         delete_session(State(state.clone()), Path("delete-me".to_string())).await;
         
         let Json(sessions) = list_sessions(State(state.clone())).await;
         assert_eq!(sessions.len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_create_session_limits_return_429() {
+        let (tx, _) = broadcast::channel(10);
+        let limits = SessionLimits { max_sessions: Some(2), max_sessions_per_user: Some(1), ..SessionLimits::default() };
+        let registry = Arc::new(SessionRegistry::with_limits(tx.clone(), limits));
+        let db = Db::new("sqlite::memory:").await.unwrap();
+        let state = Arc::new(AppState { registry, tx, db, config: Config::default() });
+
+        let req = |id: &str| Json(CreateSessionRequest { id: Some(id.to_string()), ..Default::default() });
+
+        let response = create_session(State(state.clone()), user(1), req("a")).await.into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Vượt giới hạn theo user
+        let response = create_session(State(state.clone()), user(1), req("b")).await.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(json["error"].as_str().unwrap().contains("per-user"));
+
+        let response = create_session(State(state.clone()), user(2), req("c")).await.into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Vượt giới hạn toàn server
+        let response = create_session(State(state.clone()), user(3), req("d")).await.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(state.registry.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_as_unix_user_requires_mapping() {
+        let (tx, _) = broadcast::channel(10);
+        let registry = Arc::new(SessionRegistry::new(tx.clone()));
+        let db = Db::new("sqlite::memory:").await.unwrap();
+        let config = Config { spawn_as_unix_user: true, ..Config::default() };
+        let state = Arc::new(AppState { registry, tx, db, config });
+
+        // Chưa map: từ chối thay vì chạy shell dưới quyền server
+        let req = Json(CreateSessionRequest { id: Some("unmapped".to_string()), ..Default::default() });
+        let response = create_session(State(state.clone()), user(1), req).await.into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(json_body(response).await["error"].as_str().unwrap().contains("unix account"));
+        assert_eq!(state.registry.len(), 0);
+
+        let member = state.db.create_user("dev", "hash", "member").await.unwrap();
+        let admin = || Extension(CurrentUser(User { role: "admin".to_string(), ..user(99).0 .0 }));
+        let body = |name: Option<&str>| Json(UnixUserRequest { unix_user: name.map(str::to_string) });
+
+        let response = set_unix_user(State(state.clone()), user(1), Path(member.id), body(Some("nobody"))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = set_unix_user(State(state.clone()), admin(), Path(member.id), body(Some("no-such-user-bterm"))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = set_unix_user(State(state.clone()), admin(), Path(9999), body(Some("nobody"))).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = set_unix_user(State(state.clone()), admin(), Path(member.id), body(Some("nobody"))).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let mapped = state.db.get_user_by_id(member.id).await.unwrap().unwrap();
+        assert_eq!(mapped.unix_user.as_deref(), Some("nobody"));
+
+        let response = set_unix_user(State(state.clone()), admin(), Path(member.id), body(None)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(state.db.get_user_by_id(member.id).await.unwrap().unwrap().unix_user.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_ssh_session_errors_and_host_list() {
+        let dir = std::env::temp_dir().join(format!("bterm-api-ssh-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).unwrap();
+        let hosts = serde_json::json!([
+            {"name": "down", "host": "127.0.0.1", "port": 1, "user": "ops", "host_key": "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIIcaFdoqKlnzZqpH8t1tdEth6Ogv7AfMYYopvIKraguL", "password": "pw"},
+            {"name": "private", "host": "10.0.0.1", "user": "ops", "host_key": "x", "key": "k", "users": ["someone-else"]},
+        ]);
+        std::fs::write(dir.join(crate::ssh::HOSTS_FILE), hosts.to_string()).unwrap();
+
+        let state = setup().await;
+        let req = |host: &str| Json(CreateSessionRequest { id: Some("jump".to_string()), ssh: Some(host.to_string()), ..Default::default() });
+        // Chưa cấu hình keystore
+        let response = create_session(State(state.clone()), user(1), req("down")).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let (tx, _) = broadcast::channel(10);
+        let registry = Arc::new(SessionRegistry::new(tx.clone()));
+        let db = Db::new("sqlite::memory:").await.unwrap();
+        let config = Config { ssh_keystore: Some(dir.clone()), ..Config::default() };
+        let state = Arc::new(AppState { registry, tx, db, config });
+
+        let response = create_session(State(state.clone()), user(1), req("down")).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let response = create_session(State(state.clone()), user(1), req("private")).await.into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = create_session(State(state.clone()), user(1), req("missing")).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.registry.len(), 0);
+
+        let listed = json_body(list_ssh_hosts(State(state), user(1)).await).await;
+        assert_eq!(listed, serde_json::json!([{"name": "down", "host": "127.0.0.1", "port": 1, "user": "ops"}]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_templates_crud_and_create_from_template() {
+        let dir = std::env::temp_dir().join(format!("bterm-api-rec-{}", uuid::Uuid::new_v4().simple()));
+        let (tx, _) = broadcast::channel(10);
+        let limits = SessionLimits { recording_dir: Some(dir.clone()), ..SessionLimits::default() };
+        let registry = Arc::new(SessionRegistry::with_limits(tx.clone(), limits));
+        let db = Db::new("sqlite::memory:").await.unwrap();
+        let owner = db.create_user("owner", "hash", "member").await.unwrap();
+        let state = Arc::new(AppState { registry, tx, db, config: Config::default() });
+        let as_owner = || user(owner.id);
+
+        let template = SessionTemplate {
+            name: "greeting".to_string(),
+            command: Some("/bin/sh".to_string()),
+            args: vec!["-c".to_string(), "echo \"$GREETING from $(pwd)\"; sleep 30".to_string()],
+            cwd: Some("/tmp".to_string()),
+            env: [("GREETING".to_string(), "hi".to_string())].into(),
+            rows: Some(30),
+            cols: Some(100),
+            recording: true,
+            idle_timeout_mins: Some(5),
+        };
+        let response = create_template(State(state.clone()), as_owner(), Json(template.clone())).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = create_template(State(state.clone()), as_owner(), Json(template.clone())).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let invalid = SessionTemplate { cwd: Some("tmp".to_string()), ..template.clone() };
+        let response = create_template(State(state.clone()), as_owner(), Json(invalid)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Template thuộc về từng user
+        let listed = json_body(list_templates(State(state.clone()), as_owner()).await).await;
+        assert_eq!(listed[0]["name"], "greeting");
+        assert_eq!(listed[0]["env"]["GREETING"], "hi");
+        assert_eq!(json_body(list_templates(State(state.clone()), user(owner.id + 1)).await).await, serde_json::json!([]));
+        let response = get_template(State(state.clone()), user(owner.id + 1), Path("greeting".to_string())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let req = |template: &str| Json(CreateSessionRequest { id: Some("from-tpl".to_string()), template: Some(template.to_string()), ..Default::default() });
+        let response = create_session(State(state.clone()), as_owner(), req("missing")).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = create_session(State(state.clone()), as_owner(), req("greeting")).await.into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = json_body(response).await;
+        assert!(body["command"].as_str().unwrap().starts_with("/bin/sh -c"));
+        assert_eq!(body["cwd"], "/tmp");
+        assert_eq!(body["size"], serde_json::json!({"rows": 30, "cols": 100}));
+        assert_eq!(body["recording"], true);
+
+        let session = state.registry.get_session("from-tpl").unwrap();
+        assert_eq!(session.idle_timeout, Some(std::time::Duration::from_mins(5)));
+        for _ in 0..100 {
+            if String::from_utf8_lossy(&session.history.lock().unwrap().snapshot()).contains("hi from /tmp") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(String::from_utf8_lossy(&session.history.lock().unwrap().snapshot()).contains("hi from /tmp"));
+        let recording = std::fs::read_to_string(session.recording.clone().unwrap()).unwrap();
+        assert!(recording.lines().next().unwrap().contains(r#""width":100"#));
+        assert!(recording.contains("hi from /tmp"));
+        state.registry.terminate_session("from-tpl");
+
+        // Đổi tên rồi xóa
+        let renamed = SessionTemplate { name: "greet".to_string(), recording: false, ..template };
+        let response = update_template(State(state.clone()), as_owner(), Path("greeting".to_string()), Json(renamed.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = update_template(State(state.clone()), as_owner(), Path("greeting".to_string()), Json(renamed)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let fetched = json_body(get_template(State(state.clone()), as_owner(), Path("greet".to_string())).await).await;
+        assert_eq!(fetched["recording"], false);
+        let response = delete_template(State(state.clone()), as_owner(), Path("greet".to_string())).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = delete_template(State(state.clone()), as_owner(), Path("greet".to_string())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Recording cần `SESSION_RECORDING_DIR`
+        let plain = setup().await;
+        let member = plain.db.create_user("member", "hash", "member").await.unwrap();
+        let recorded = SessionTemplate { name: "rec".to_string(), recording: true, ..SessionTemplate::default() };
+        assert_eq!(create_template(State(plain.clone()), user(member.id), Json(recorded)).await.status(), StatusCode::CREATED);
+        let req = Json(CreateSessionRequest { template: Some("rec".to_string()), ..Default::default() });
+        let response = create_session(State(plain.clone()), user(member.id), req).await.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(json_body(response).await["error"].as_str().unwrap().contains("recording"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_clone_session() {
+        let state = setup().await;
+        let options = SessionOptions::new("orig".to_string())
+            .owner(1)
+            .command("/bin/sh", Vec::new())
+            .envs([("STAGE".to_string(), "prod".to_string())]);
+        let source = state.registry.create_session(options).unwrap();
+        source.backend.write(b"cd /var\n").unwrap();
+        for _ in 0..100 {
+            if source.current_dir().as_deref() == Some("/var") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let response = clone_session(State(state.clone()), user(2), Path("orig".to_string()), None).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = clone_session(State(state.clone()), user(1), Path("missing".to_string()), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let req = Json(CloneSessionRequest { id: Some("copy".to_string()), name: Some("Copy".to_string()) });
+        let response = clone_session(State(state.clone()), user(1), Path("orig".to_string()), Some(req)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = json_body(response).await;
+        assert_eq!(body["id"], "copy");
+        assert_eq!(body["name"], "Copy");
+        assert_eq!(body["command"], "/bin/sh");
+        assert_eq!(body["cwd"], "/var");
+
+        let copy = state.registry.get_session("copy").unwrap();
+        copy.backend.write(b"echo \"$STAGE@$(pwd)\"\n").unwrap();
+        for _ in 0..100 {
+            if String::from_utf8_lossy(&copy.history.lock().unwrap().snapshot()).contains("prod@/var") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(String::from_utf8_lossy(&copy.history.lock().unwrap().snapshot()).contains("prod@/var"));
+
+        // Không có body: ID tự sinh
+        let response = clone_session(State(state.clone()), user(1), Path("orig".to_string()), None).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(state.registry.len(), 3);
+        for info in state.registry.list_sessions() {
+            state.registry.terminate_session(&info.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_session() {
+        let state = setup().await;
+        let session = state.registry.create_session(SessionOptions::new("grep".to_string()).owner(1).command("sleep", vec!["30".to_string()])).unwrap();
+        session.history.lock().unwrap().push(
+            b"\x1b[01;32m$\x1b[0m make\r\ncc main.c\r\n\x1b[1;31merror\x1b[0m: x undeclared\r\n1 Error\r\n",
+        );
+        let query = |q: &str, regex: bool| Query(SearchQuery { q: q.to_string(), regex, ignore_case: false, context: None, limit: None });
+        let search = |user_id: i64, id: &str, query| search_session(State(state.clone()), user(user_id), Path(id.to_string()), query);
+
+        let response = search(1, "grep", query("error:", false)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["total_lines"], 4);
+        assert_eq!(body["truncated"], false);
+        assert_eq!(
+            body["matches"],
+            serde_json::json!([{
+                "line": 2,
+                "text": "error: x undeclared",
+                "ranges": [[0, 6]],
+                "before": ["$ make", "cc main.c"],
+                "after": ["1 Error"],
+            }])
+        );
+
+        let body = json_body(search(1, "grep", query(r"(?i)^\d+ error$", true)).await).await;
+        assert_eq!(body["matches"][0]["line"], 3);
+        let limited = Query(SearchQuery { limit: Some(1), context: Some(0), ignore_case: true, ..query("error", false).0 });
+        let body = json_body(search(1, "grep", limited).await).await;
+        assert_eq!(body["matches"].as_array().unwrap().len(), 1);
+        assert_eq!(body["matches"][0]["before"], serde_json::json!([]));
+        assert_eq!(body["truncated"], true);
+
+        assert_eq!(search(1, "grep", query("(", true)).await.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(search(1, "grep", query("", false)).await.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(search(2, "grep", query("error", false)).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(search(1, "missing", query("error", false)).await.status(), StatusCode::NOT_FOUND);
+        state.registry.terminate_session("grep");
+    }
+
+    #[tokio::test]
+    async fn test_session_scrollback_formats() {
+        let state = setup().await;
+        let options = SessionOptions::new("log".to_string()).owner(1).name("Build <1>".to_string()).command("sleep", vec!["30".to_string()]);
+        let session = state.registry.create_session(options).unwrap();
+        session.history.lock().unwrap().push(b"\x1b[31mfail\x1b[0m\r\n");
+        let scrollback = |user_id: i64, id: &str, format| {
+            session_scrollback(State(state.clone()), user(user_id), Path(id.to_string()), Query(ScrollbackQuery { format }))
+        };
+        let body = |response: Response| async { axum::body::to_bytes(response.into_body(), 1024 * 1024).await.unwrap() };
+
+        let response = scrollback(1, "log", ScrollbackFormat::Raw).await;
+        assert_eq!(response.headers()["content-type"], "application/octet-stream");
+        assert_eq!(body(response).await.as_ref(), b"\x1b[31mfail\x1b[0m\r\n");
+
+        let response = scrollback(1, "log", ScrollbackFormat::Text).await;
+        assert_eq!(response.headers()["content-type"], "text/plain; charset=utf-8");
+        assert_eq!(body(response).await.as_ref(), b"fail\n");
+
+        let response = scrollback(1, "log", ScrollbackFormat::Html).await;
+        assert_eq!(response.headers()["content-type"], "text/html; charset=utf-8");
+        let html = String::from_utf8(body(response).await.to_vec()).unwrap();
+        assert!(html.contains("<title>Build &lt;1&gt;</title>"));
+        assert!(html.contains("<span style=\"color:#cd0000;\">fail</span>"));
+
+        assert_eq!(scrollback(2, "log", ScrollbackFormat::Raw).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(scrollback(1, "missing", ScrollbackFormat::Raw).await.status(), StatusCode::NOT_FOUND);
+        state.registry.terminate_session("log");
+    }
 }
@@ -17,6 +17,10 @@ use crate::{AppState, db::User};
 
 pub const SESSION_USER_KEY: &str = "user_id";
 
+/// User đã xác thực, được `require_auth` gắn vào request extensions cho các handler phía sau.
+#[derive(Clone, Debug)]
+pub struct CurrentUser(pub User);
+
 #[derive(Deserialize)]
 pub struct LoginRequest {
     username: String,
@@ -29,6 +33,7 @@ pub struct UserResponse {
     pub username: String,
     pub role: String,
     pub must_change_password: bool,
+    pub unix_user: Option<String>,
 }
 
 impl From<User> for UserResponse {
@@ -38,6 +43,7 @@ impl From<User> for UserResponse {
             username: user.username,
             role: user.role,
             must_change_password: user.must_change_password,
+            unix_user: user.unix_user,
         }
     }
 }
@@ -74,11 +80,20 @@ pub async fn login(
 ) -> impl IntoResponse {
     let user = match state.db.get_user_by_username(&payload.username).await {
         Ok(Some(u)) => u,
-        Ok(None) => return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(),
-        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        Ok(None) => {
+            tracing::warn!(username = %payload.username, "login failed: unknown user");
+            crate::metrics::LOGINS.with_label_values(&["failure"]).inc();
+            return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "login failed: database error");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response();
+        }
     };
 
     if !verify_password(&payload.password, &user.password_hash) {
+        tracing::warn!(username = %payload.username, "login failed: wrong password");
+        crate::metrics::LOGINS.with_label_values(&["failure"]).inc();
         return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
     }
 
@@ -86,6 +101,10 @@ pub async fn login(
         return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create session").into_response();
     }
 
+    tracing::Span::current().record("user_id", user.id);
+    tracing::info!(username = %user.username, "login succeeded");
+    crate::metrics::LOGINS.with_label_values(&["success"]).inc();
+
     (StatusCode::OK, Json(UserResponse::from(user))).into_response()
 }
 
@@ -132,15 +151,18 @@ pub async fn change_password(
         Some(id) => id,
         None => return (StatusCode::UNAUTHORIZED, "Not authenticated").into_response(),
     };
+    tracing::Span::current().record("user_id", user_id);
 
     let new_hash = match hash_password(&payload.new_password) {
         Ok(h) => h,
         Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password").into_response(),
     };
 
-    if let Err(_) = state.db.update_password(user_id, &new_hash).await {
+    if let Err(e) = state.db.update_password(user_id, &new_hash).await {
+        tracing::error!(error = %e, "failed to update password");
         return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response();
     }
+    tracing::info!("password changed");
 
     // Get updated user
     match state.db.get_user_by_id(user_id).await {
@@ -153,7 +175,7 @@ pub async fn change_password(
 pub async fn require_auth(
     State(state): State<Arc<AppState>>,
     session: Session,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
     let user_id: Option<i64> = session.get(SESSION_USER_KEY).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -161,6 +183,7 @@ pub async fn require_auth(
         Some(id) => id,
         None => return Err(StatusCode::UNAUTHORIZED),
     };
+    tracing::Span::current().record("user_id", user_id);
 
     // Check if user must change password
     match state.db.get_user_by_id(user_id).await {
@@ -170,6 +193,7 @@ pub async fn require_auth(
                 // Since this middleware is only for protected_routes, we return 403.
                 return Err(StatusCode::FORBIDDEN);
             }
+            request.extensions_mut().insert(CurrentUser(user));
         }
         _ => return Err(StatusCode::UNAUTHORIZED),
     }
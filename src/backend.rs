@@ -0,0 +1,254 @@
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use crate::foreground::ForegroundProcess;
+use crate::output::ReaderOptions;
+use crate::pty_manager::{ProcessEntry, PtyManager, ResourceUsage, SessionSignal, SignalTarget, SpawnOptions};
+
+/// Nguồn I/O của một session: PTY cục bộ, SSH, container attach, ...
+///
+/// Output được đẩy vào broadcast channel của session; một `Vec` rỗng báo backend đã kết thúc.
+/// Các hàm về tiến trình có mặc định "không hỗ trợ" cho backend không chạy tiến trình cục bộ.
+pub trait SessionBackend: Send + Sync {
+    /// Loại backend, hiển thị trong `SessionInfo::backend` (ví dụ `pty`).
+    fn kind(&self) -> &'static str;
+
+    /// Lệnh hoặc đích kết nối hiển thị cho người dùng.
+    fn command(&self) -> &str;
+
+    /// Thư mục làm việc lúc khởi động, nếu biết.
+    fn cwd(&self) -> Option<&str> {
+        None
+    }
+
+    /// PID của tiến trình cục bộ (shell), nếu có.
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+
+    /// Bắt đầu đọc output vào `tx`. Chỉ có tác dụng ở lần gọi đầu tiên. Backend nên gộp output
+    /// theo `options.coalesce` và ngừng đọc khi `options.flow` tạm dừng, nếu có thể.
+    fn start_reader(&self, tx: broadcast::Sender<Vec<u8>>, options: ReaderOptions);
+
+    /// # Errors
+    /// Lỗi nếu không ghi được input.
+    fn write(&self, data: &[u8]) -> anyhow::Result<()>;
+
+    /// # Errors
+    /// Lỗi nếu backend không đổi được kích thước terminal.
+    fn resize(&self, rows: u16, cols: u16) -> anyhow::Result<()>;
+
+    /// Kích thước terminal hiện tại (rows, cols).
+    fn size(&self) -> Option<(u16, u16)>;
+
+    /// Gửi tín hiệu, trả về các process group đã nhận.
+    ///
+    /// # Errors
+    /// Lỗi nếu backend không gửi được tín hiệu.
+    fn signal(&self, signal: SessionSignal, target: SignalTarget) -> anyhow::Result<Vec<u32>>;
+
+    /// Exit code khi backend đã kết thúc.
+    fn exit_status(&self) -> Option<u32>;
+
+    /// Kết thúc backend và giải phóng tài nguyên.
+    fn shutdown(&self);
+
+    fn foreground(&self) -> Option<ForegroundProcess> {
+        None
+    }
+
+    fn sample_usage(&self) -> Option<ResourceUsage> {
+        None
+    }
+
+    fn process_tree(&self) -> Vec<ProcessEntry> {
+        Vec::new()
+    }
+
+    /// # Errors
+    /// Mặc định: backend không quản lý tiến trình cục bộ.
+    fn signal_pid(&self, _pid: u32, _signal: SessionSignal) -> anyhow::Result<()> {
+        anyhow::bail!("{} sessions do not support per-process signals", self.kind())
+    }
+}
+
+/// Tạo backend cho session mới; registry gọi sau khi đã giữ chỗ trong giới hạn.
+pub trait BackendFactory: Send + Sync + std::fmt::Debug {
+    /// # Errors
+    /// Lỗi nếu không khởi động được backend.
+    fn spawn(&self, options: &SpawnOptions) -> anyhow::Result<Arc<dyn SessionBackend>>;
+}
+
+/// Backend mặc định: shell trên PTY cục bộ.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalPty;
+
+impl BackendFactory for LocalPty {
+    fn spawn(&self, options: &SpawnOptions) -> anyhow::Result<Arc<dyn SessionBackend>> {
+        Ok(Arc::new(PtyManager::spawn(options)?))
+    }
+}
+
+impl SessionBackend for PtyManager {
+    fn kind(&self) -> &'static str {
+        "pty"
+    }
+
+    fn command(&self) -> &str {
+        Self::command(self)
+    }
+
+    fn cwd(&self) -> Option<&str> {
+        Self::cwd(self)
+    }
+
+    fn pid(&self) -> Option<u32> {
+        Self::pid(self)
+    }
+
+    fn start_reader(&self, tx: broadcast::Sender<Vec<u8>>, options: ReaderOptions) {
+        Self::start_reader_with(self, tx, options);
+    }
+
+    fn write(&self, data: &[u8]) -> anyhow::Result<()> {
+        Self::write(self, data)
+    }
+
+    fn resize(&self, rows: u16, cols: u16) -> anyhow::Result<()> {
+        Self::resize(self, rows, cols)
+    }
+
+    fn size(&self) -> Option<(u16, u16)> {
+        Self::size(self)
+    }
+
+    fn signal(&self, signal: SessionSignal, target: SignalTarget) -> anyhow::Result<Vec<u32>> {
+        Self::signal(self, signal, target)
+    }
+
+    fn exit_status(&self) -> Option<u32> {
+        Self::exit_status(self)
+    }
+
+    fn shutdown(&self) {
+        Self::shutdown(self);
+    }
+
+    fn foreground(&self) -> Option<ForegroundProcess> {
+        crate::foreground::detect(self)
+    }
+
+    fn sample_usage(&self) -> Option<ResourceUsage> {
+        Self::sample_usage(self)
+    }
+
+    fn process_tree(&self) -> Vec<ProcessEntry> {
+        Self::process_tree(self)
+    }
+
+    fn signal_pid(&self, pid: u32, signal: SessionSignal) -> anyhow::Result<()> {
+        Self::signal_pid(self, pid, signal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::session::{SessionOptions, SessionRegistry};
+
+    /// Backend giả cho test: echo lại input, ghi nhận resize, không spawn tiến trình.
+    #[derive(Default)]
+    struct ScriptedBackend {
+        tx: Mutex<Option<broadcast::Sender<Vec<u8>>>>,
+        /// Output được phát ngay khi reader bắt đầu.
+        banner: Vec<u8>,
+        size: Mutex<Option<(u16, u16)>>,
+        exit_status: Mutex<Option<u32>>,
+    }
+
+    impl SessionBackend for ScriptedBackend {
+        fn kind(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn command(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn start_reader(&self, tx: broadcast::Sender<Vec<u8>>, _options: ReaderOptions) {
+            if !self.banner.is_empty() {
+                let _ = tx.send(self.banner.clone());
+            }
+            *self.tx.lock().unwrap() = Some(tx);
+        }
+
+        fn write(&self, data: &[u8]) -> anyhow::Result<()> {
+            let tx = self.tx.lock().unwrap();
+            let tx = tx.as_ref().ok_or_else(|| anyhow::anyhow!("backend has exited"))?;
+            let _ = tx.send(data.to_vec());
+            Ok(())
+        }
+
+        fn resize(&self, rows: u16, cols: u16) -> anyhow::Result<()> {
+            *self.size.lock().unwrap() = Some((rows, cols));
+            Ok(())
+        }
+
+        fn size(&self) -> Option<(u16, u16)> {
+            *self.size.lock().unwrap()
+        }
+
+        fn signal(&self, _signal: SessionSignal, _target: SignalTarget) -> anyhow::Result<Vec<u32>> {
+            anyhow::bail!("no processes")
+        }
+
+        fn exit_status(&self) -> Option<u32> {
+            *self.exit_status.lock().unwrap()
+        }
+
+        fn shutdown(&self) {
+            let tx = self.tx.lock().unwrap().take();
+            if let Some(tx) = tx {
+                let _ = tx.send(Vec::new());
+            }
+            self.exit_status.lock().unwrap().get_or_insert(0);
+        }
+    }
+
+    #[derive(Debug)]
+    struct ScriptedFactory(&'static [u8]);
+
+    impl BackendFactory for ScriptedFactory {
+        fn spawn(&self, _options: &SpawnOptions) -> anyhow::Result<Arc<dyn SessionBackend>> {
+            Ok(Arc::new(ScriptedBackend { banner: self.0.to_vec(), ..ScriptedBackend::default() }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_with_scripted_backend() {
+        let (tx, _) = broadcast::channel(10);
+        let registry = SessionRegistry::new(tx);
+        let options = SessionOptions::new("fake".to_string()).backend(Arc::new(ScriptedFactory(b"\x1b]2;fake title\x07hello ")));
+        let session = registry.create_session(options).unwrap();
+
+        session.backend.write(b"world").unwrap();
+        session.update_client_size(uuid::Uuid::new_v4(), 30, 100);
+        for _ in 0..100 {
+            if session.history.lock().unwrap().snapshot().ends_with(b"hello world") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(session.history.lock().unwrap().snapshot().ends_with(b"hello world"));
+
+        let info = session.info();
+        assert_eq!(info.backend, "scripted");
+        assert_eq!(info.title.as_deref(), Some("fake title"));
+        assert_eq!(info.size.map(|s| (s.rows, s.cols)), Some((30, 100)));
+        assert!(info.pid.is_none() && info.foreground.is_none());
+        assert!(session.backend.signal_pid(1, SessionSignal::Term).is_err());
+
+        registry.terminate_session("fake");
+        assert_eq!(session.backend.exit_status(), Some(0));
+    }
+}
@@ -0,0 +1,337 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Cấu hình runtime của server, đọc từ biến môi trường khi khởi động.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Bearer token bảo vệ endpoint `/metrics`. `None` = không yêu cầu xác thực.
+    pub metrics_token: Option<String>,
+    /// Chạy shell dưới tài khoản Unix được gán cho user (`SPAWN_AS_UNIX_USER`).
+    pub spawn_as_unix_user: bool,
+    /// Thư mục keystore chứa `hosts.json` và khóa SSH (`SSH_KEYSTORE_DIR`). `None` = tắt session SSH.
+    pub ssh_keystore: Option<PathBuf>,
+}
+
+impl Config {
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            metrics_token: env_string("METRICS_TOKEN"),
+            spawn_as_unix_user: env_parse("SPAWN_AS_UNIX_USER").unwrap_or(false),
+            ssh_keystore: env_string("SSH_KEYSTORE_DIR").map(PathBuf::from),
+        }
+    }
+}
+
+/// Kích thước lịch sử mặc định của mỗi session (100KB).
+pub const DEFAULT_HISTORY_SIZE: usize = 102_400;
+/// Dung lượng lịch sử tối đa ghi ra đĩa mặc định của mỗi session khi bật spill (16MB).
+pub const DEFAULT_SPILL_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Khoảng gộp output PTY mặc định.
+pub const DEFAULT_OUTPUT_COALESCE: Duration = Duration::from_millis(5);
+/// Số byte output chờ gửi tối đa mặc định của mỗi WebSocket client (1MB).
+pub const DEFAULT_CLIENT_QUEUE_BYTES: usize = 1024 * 1024;
+
+/// Giới hạn tài nguyên cho session. `None` = không giới hạn.
+#[derive(Debug, Clone)]
+pub struct SessionLimits {
+    /// Tổng số session tối đa trên toàn server (`MAX_SESSIONS`).
+    pub max_sessions: Option<usize>,
+    /// Số session tối đa mỗi user được sở hữu (`MAX_SESSIONS_PER_USER`).
+    pub max_sessions_per_user: Option<usize>,
+    /// Số WebSocket client tối đa trên một session (`MAX_CLIENTS_PER_SESSION`).
+    pub max_clients_per_session: Option<usize>,
+    /// Số byte lịch sử output giữ lại cho mỗi session (`SESSION_HISTORY_BYTES`).
+    pub history_size: usize,
+    /// Thư mục chứa file spill: phần lịch sử bị đẩy khỏi bộ nhớ được ghi tiếp ra đĩa (`SESSION_SPILL_DIR`).
+    /// `None` = chỉ giữ lịch sử trong bộ nhớ.
+    pub spill_dir: Option<PathBuf>,
+    /// Số byte lịch sử tối đa ghi ra đĩa cho mỗi session (`SESSION_SPILL_BYTES`).
+    pub spill_size: u64,
+    /// Cách output được chuyển tới client.
+    pub output: OutputLimits,
+    /// Giới hạn tài nguyên áp dụng cho shell của mỗi session.
+    pub shell: ShellLimits,
+    /// Biến môi trường và chế độ login của shell.
+    pub environment: ShellEnvironment,
+    /// Thư mục lưu bản ghi asciicast của session bật recording (`SESSION_RECORDING_DIR`).
+    /// `None` = không cho phép recording.
+    pub recording_dir: Option<PathBuf>,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            max_sessions: None,
+            max_sessions_per_user: None,
+            max_clients_per_session: None,
+            history_size: DEFAULT_HISTORY_SIZE,
+            spill_dir: None,
+            spill_size: DEFAULT_SPILL_SIZE,
+            output: OutputLimits::default(),
+            shell: ShellLimits::default(),
+            environment: ShellEnvironment::default(),
+            recording_dir: None,
+        }
+    }
+}
+
+impl SessionLimits {
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            max_sessions: env_parse("MAX_SESSIONS"),
+            max_sessions_per_user: env_parse("MAX_SESSIONS_PER_USER"),
+            max_clients_per_session: env_parse("MAX_CLIENTS_PER_SESSION"),
+            history_size: env_parse("SESSION_HISTORY_BYTES").unwrap_or(DEFAULT_HISTORY_SIZE),
+            spill_dir: env_string("SESSION_SPILL_DIR").map(PathBuf::from),
+            spill_size: env_parse("SESSION_SPILL_BYTES").unwrap_or(DEFAULT_SPILL_SIZE),
+            output: OutputLimits::from_env(),
+            shell: ShellLimits::from_env(),
+            environment: ShellEnvironment::from_env(),
+            recording_dir: env_string("SESSION_RECORDING_DIR").map(PathBuf::from),
+        }
+    }
+}
+
+/// Giới hạn rlimit (đặt cả soft và hard) áp dụng cho shell trước khi exec.
+/// `None` = kế thừa giới hạn của server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShellLimits {
+    /// `RLIMIT_CPU`, giây CPU (`SHELL_RLIMIT_CPU_SECS`).
+    pub cpu_secs: Option<u64>,
+    /// `RLIMIT_AS`, byte (`SHELL_RLIMIT_AS_BYTES`).
+    pub address_space_bytes: Option<u64>,
+    /// `RLIMIT_NPROC`, tính theo UID thực (`SHELL_RLIMIT_NPROC`).
+    pub max_processes: Option<u64>,
+    /// `RLIMIT_NOFILE` (`SHELL_RLIMIT_NOFILE`).
+    pub open_files: Option<u64>,
+    /// `RLIMIT_CORE`, byte (`SHELL_RLIMIT_CORE_BYTES`).
+    pub core_bytes: Option<u64>,
+    /// cgroup v2 riêng cho mỗi session (`SESSION_CGROUP_PARENT`).
+    pub cgroup: Option<CgroupLimits>,
+}
+
+/// Mỗi session được đặt vào `<parent>/session-<id>` với các giới hạn dưới đây.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CgroupLimits {
+    /// cgroup cha đã được delegate cho server, bật sẵn controller `memory` và `pids`.
+    pub parent: PathBuf,
+    /// `memory.max`, byte (`SESSION_CGROUP_MEMORY_MAX`).
+    pub memory_max: Option<u64>,
+    /// `pids.max` (`SESSION_CGROUP_PIDS_MAX`).
+    pub pids_max: Option<u64>,
+}
+
+impl ShellLimits {
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            cpu_secs: env_parse("SHELL_RLIMIT_CPU_SECS"),
+            address_space_bytes: env_parse("SHELL_RLIMIT_AS_BYTES"),
+            max_processes: env_parse("SHELL_RLIMIT_NPROC"),
+            open_files: env_parse("SHELL_RLIMIT_NOFILE"),
+            core_bytes: env_parse("SHELL_RLIMIT_CORE_BYTES"),
+            cgroup: env_string("SESSION_CGROUP_PARENT").map(|parent| CgroupLimits {
+                parent: PathBuf::from(parent),
+                memory_max: env_parse("SESSION_CGROUP_MEMORY_MAX"),
+                pids_max: env_parse("SESSION_CGROUP_PIDS_MAX"),
+            }),
+        }
+    }
+}
+
+/// Biến cấu hình/bí mật của server, không bao giờ được truyền vào shell.
+pub const SERVER_ENV_VARS: &[&str] = &["DATABASE_URL", "METRICS_TOKEN", "SKIP_ADMIN_PWD_CHANGE", "SPAWN_AS_UNIX_USER", "SSH_KEYSTORE_DIR"];
+
+/// Biến được giữ lại khi bật `SHELL_CLEAN_ENV` mà không đặt `SHELL_ENV_ALLOWLIST`.
+pub const DEFAULT_ENV_ALLOWLIST: &[&str] = &["PATH", "LANG", "LC_*", "TZ"];
+
+/// Gộp output và kiểm soát client chậm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// Gộp các lần đọc PTY tới trong khoảng này thành một message (`OUTPUT_COALESCE_MS`, `0` = không chờ).
+    pub coalesce: Duration,
+    /// Số byte output chờ gửi tối đa của mỗi WebSocket client; vượt quá thì bỏ hàng đợi và gửi lại
+    /// từ lịch sử (`CLIENT_QUEUE_BYTES`).
+    pub client_queue_bytes: usize,
+    /// Tạm dừng đọc PTY khi mọi client đều tụt lại quá nửa hàng đợi (`PAUSE_OUTPUT_WHEN_BEHIND`).
+    pub pause_when_behind: bool,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self {
+            coalesce: DEFAULT_OUTPUT_COALESCE,
+            client_queue_bytes: DEFAULT_CLIENT_QUEUE_BYTES,
+            pause_when_behind: false,
+        }
+    }
+}
+
+impl OutputLimits {
+    #[must_use]
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            coalesce: env_parse("OUTPUT_COALESCE_MS").map_or(defaults.coalesce, Duration::from_millis),
+            client_queue_bytes: env_parse("CLIENT_QUEUE_BYTES").unwrap_or(defaults.client_queue_bytes),
+            pause_when_behind: env_parse("PAUSE_OUTPUT_WHEN_BEHIND").unwrap_or(defaults.pause_when_behind),
+        }
+    }
+}
+
+/// Môi trường khởi động của shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellEnvironment {
+    /// Bắt đầu từ môi trường rỗng, chỉ kế thừa các biến trong `allowlist` (`SHELL_CLEAN_ENV`).
+    pub clean: bool,
+    /// Tên biến được kế thừa; `*` ở cuối là prefix, ví dụ `LC_*` (`SHELL_ENV_ALLOWLIST`, phân tách bằng dấu phẩy).
+    pub allowlist: Vec<String>,
+    /// Chạy shell ở chế độ login (argv0 có `-` ở đầu) (`SHELL_LOGIN`).
+    pub login: bool,
+}
+
+impl Default for ShellEnvironment {
+    fn default() -> Self {
+        Self {
+            clean: false,
+            allowlist: DEFAULT_ENV_ALLOWLIST.iter().map(ToString::to_string).collect(),
+            login: false,
+        }
+    }
+}
+
+impl ShellEnvironment {
+    #[must_use]
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            clean: env_parse("SHELL_CLEAN_ENV").unwrap_or(defaults.clean),
+            allowlist: env_string("SHELL_ENV_ALLOWLIST").map_or(defaults.allowlist, |list| {
+                list.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()
+            }),
+            login: env_parse("SHELL_LOGIN").unwrap_or(defaults.login),
+        }
+    }
+
+    /// Lọc môi trường của server thành các biến được truyền vào shell.
+    pub fn inherited<I>(&self, vars: I) -> Vec<(OsString, OsString)>
+    where
+        I: IntoIterator<Item = (OsString, OsString)>,
+    {
+        vars.into_iter()
+            .filter(|(key, _)| {
+                let Some(key) = key.to_str() else { return !self.clean };
+                !SERVER_ENV_VARS.contains(&key) && (!self.clean || self.allows(key))
+            })
+            .collect()
+    }
+
+    fn allows(&self, key: &str) -> bool {
+        self.allowlist
+            .iter()
+            .any(|pattern| pattern.strip_suffix('*').map_or_else(|| key == pattern, |prefix| key.starts_with(prefix)))
+    }
+}
+
+/// Chính sách tự động dọn session không hoạt động. `None` = tắt chính sách tương ứng.
+#[derive(Debug, Clone)]
+pub struct IdlePolicy {
+    /// Kết thúc session không có input/output trong khoảng này (`SESSION_IDLE_TIMEOUT_MINS`).
+    pub idle_timeout: Option<Duration>,
+    /// Kết thúc session không có client nào gắn vào trong khoảng này (`SESSION_DETACHED_TIMEOUT_MINS`).
+    pub detached_timeout: Option<Duration>,
+    /// Cảnh báo client đang gắn vào trước khi kết thúc (`SESSION_IDLE_WARNING_SECS`).
+    pub warning: Duration,
+    /// Chu kỳ kiểm tra của reaper.
+    pub check_interval: Duration,
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self {
+            idle_timeout: None,
+            detached_timeout: None,
+            warning: Duration::from_mins(1),
+            check_interval: Duration::from_secs(15),
+        }
+    }
+}
+
+impl IdlePolicy {
+    #[must_use]
+    pub fn from_env() -> Self {
+        let minutes = |key| env_parse::<u64>(key).filter(|m| *m > 0).map(|m| Duration::from_secs(m * 60));
+        Self {
+            idle_timeout: minutes("SESSION_IDLE_TIMEOUT_MINS"),
+            detached_timeout: minutes("SESSION_DETACHED_TIMEOUT_MINS"),
+            warning: env_parse("SESSION_IDLE_WARNING_SECS").map_or_else(|| Self::default().warning, Duration::from_secs),
+            ..Self::default()
+        }
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        self.idle_timeout.is_some() || self.detached_timeout.is_some()
+    }
+}
+
+/// Chu kỳ mặc định phát số liệu tài nguyên của session.
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Chu kỳ phát event `SessionStats` qua SSE (`SESSION_STATS_INTERVAL_SECS`). `None` = tắt (giá trị `0`).
+#[must_use]
+pub fn stats_interval_from_env() -> Option<Duration> {
+    match env_parse::<u64>("SESSION_STATS_INTERVAL_SECS") {
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None => Some(DEFAULT_STATS_INTERVAL),
+    }
+}
+
+/// Đọc và parse biến môi trường; giá trị không hợp lệ được bỏ qua kèm cảnh báo.
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    let raw = env_string(key)?;
+    raw.trim().parse().map_or_else(
+        |_| {
+            tracing::warn!(key, value = %raw, "ignoring invalid environment value");
+            None
+        },
+        Some,
+    )
+}
+
+/// Đọc biến môi trường dạng chuỗi, bỏ qua giá trị rỗng.
+fn env_string(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(keys: &[&str]) -> Vec<(OsString, OsString)> {
+        keys.iter().map(|k| (OsString::from(k), OsString::from("v"))).collect()
+    }
+
+    fn keys(vars: &[(OsString, OsString)]) -> Vec<&str> {
+        vars.iter().map(|(k, _)| k.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_shell_environment_filters_server_secrets() {
+        let server = vars(&["PATH", "DATABASE_URL", "METRICS_TOKEN", "LC_TIME", "AWS_SECRET_ACCESS_KEY"]);
+
+        let inherit_all = ShellEnvironment::default();
+        assert_eq!(keys(&inherit_all.inherited(server.clone())), vec!["PATH", "LC_TIME", "AWS_SECRET_ACCESS_KEY"]);
+
+        let clean = ShellEnvironment { clean: true, ..ShellEnvironment::default() };
+        assert_eq!(keys(&clean.inherited(server.clone())), vec!["PATH", "LC_TIME"]);
+
+        // Allowlist không thể mở lại biến của server
+        let allow = ShellEnvironment { clean: true, allowlist: vec!["DATABASE_URL".to_string()], login: false };
+        assert!(allow.inherited(server).is_empty());
+    }
+}
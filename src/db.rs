@@ -1,7 +1,8 @@
-use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite, Row};
+use sqlx::{sqlite::{SqlitePoolOptions, SqliteRow}, Pool, Sqlite, Row};
 use std::str::FromStr;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use crate::templates::SessionTemplate;
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
@@ -10,6 +11,9 @@ pub struct User {
     pub password_hash: String,
     pub role: String,
     pub must_change_password: bool,
+    /// Tài khoản Unix dùng để chạy shell khi bật `SPAWN_AS_UNIX_USER`.
+    #[sqlx(default)]
+    pub unix_user: Option<String>,
 }
 
 #[derive(Clone)]
@@ -60,6 +64,45 @@ impl Db {
                 .await?;
         }
 
+        let has_unix_user = sqlx::query("PRAGMA table_info(users)")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .any(|row| row.get::<String, _>("name") == "unix_user");
+
+        if !has_unix_user {
+            sqlx::query("ALTER TABLE users ADD COLUMN unix_user TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS session_templates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                owner_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                command TEXT,
+                args TEXT NOT NULL DEFAULT '[]',
+                cwd TEXT,
+                env TEXT NOT NULL DEFAULT '{}',
+                rows INTEGER,
+                cols INTEGER,
+                recording BOOLEAN NOT NULL DEFAULT 0,
+                idle_timeout_mins INTEGER,
+                UNIQUE (owner_id, name)
+            );
+            "
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Kiểm tra kết nối tới database (dùng cho `/readyz`).
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
         Ok(())
     }
 
@@ -91,6 +134,7 @@ impl Db {
             password_hash: password_hash.to_string(),
             role: role.to_string(),
             must_change_password,
+            unix_user: None,
         })
     }
 
@@ -116,6 +160,95 @@ impl Db {
         Ok(user)
     }
 
+    /// Gán (hoặc bỏ gán với `None`) tài khoản Unix cho user. Trả về `false` nếu không có user.
+    pub async fn set_unix_user(&self, user_id: i64, unix_user: Option<&str>) -> Result<bool> {
+        let result = sqlx::query("UPDATE users SET unix_user = ? WHERE id = ?")
+            .bind(unix_user)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Template của user, sắp xếp theo tên.
+    pub async fn list_templates(&self, owner_id: i64) -> Result<Vec<SessionTemplate>> {
+        sqlx::query("SELECT * FROM session_templates WHERE owner_id = ? ORDER BY name")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(template_from_row)
+            .collect()
+    }
+
+    pub async fn get_template(&self, owner_id: i64, name: &str) -> Result<Option<SessionTemplate>> {
+        sqlx::query("SELECT * FROM session_templates WHERE owner_id = ? AND name = ?")
+            .bind(owner_id)
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .as_ref()
+            .map(template_from_row)
+            .transpose()
+    }
+
+    pub async fn create_template(&self, owner_id: i64, template: &SessionTemplate) -> Result<TemplateWrite> {
+        let result = sqlx::query(
+            r"
+            INSERT INTO session_templates (owner_id, name, command, args, cwd, env, rows, cols, recording, idle_timeout_mins)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "
+        )
+        .bind(owner_id)
+        .bind(&template.name)
+        .bind(&template.command)
+        .bind(serde_json::to_string(&template.args)?)
+        .bind(&template.cwd)
+        .bind(serde_json::to_string(&template.env)?)
+        .bind(template.rows)
+        .bind(template.cols)
+        .bind(template.recording)
+        .bind(template.idle_timeout_mins.map(|m| i64::try_from(m).unwrap_or(i64::MAX)))
+        .execute(&self.pool)
+        .await;
+        template_write(result.map(|_| true))
+    }
+
+    /// Thay toàn bộ template `name` (có thể đổi tên).
+    pub async fn update_template(&self, owner_id: i64, name: &str, template: &SessionTemplate) -> Result<TemplateWrite> {
+        let result = sqlx::query(
+            r"
+            UPDATE session_templates
+            SET name = ?, command = ?, args = ?, cwd = ?, env = ?, rows = ?, cols = ?, recording = ?, idle_timeout_mins = ?
+            WHERE owner_id = ? AND name = ?
+            "
+        )
+        .bind(&template.name)
+        .bind(&template.command)
+        .bind(serde_json::to_string(&template.args)?)
+        .bind(&template.cwd)
+        .bind(serde_json::to_string(&template.env)?)
+        .bind(template.rows)
+        .bind(template.cols)
+        .bind(template.recording)
+        .bind(template.idle_timeout_mins.map(|m| i64::try_from(m).unwrap_or(i64::MAX)))
+        .bind(owner_id)
+        .bind(name)
+        .execute(&self.pool)
+        .await;
+        template_write(result.map(|r| r.rows_affected() > 0))
+    }
+
+    /// Xóa template. Trả về `false` nếu không có.
+    pub async fn delete_template(&self, owner_id: i64, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM session_templates WHERE owner_id = ? AND name = ?")
+            .bind(owner_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn update_password(&self, user_id: i64, new_hash: &str) -> Result<()> {
         sqlx::query(
             "UPDATE users SET password_hash = ?, must_change_password = 0 WHERE id = ?"
@@ -127,3 +260,35 @@ impl Db {
         Ok(())
     }
 }
+
+/// Kết quả ghi template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateWrite {
+    Saved,
+    NotFound,
+    /// User đã có template khác cùng tên.
+    NameTaken,
+}
+
+fn template_write(result: std::result::Result<bool, sqlx::Error>) -> Result<TemplateWrite> {
+    match result {
+        Ok(true) => Ok(TemplateWrite::Saved),
+        Ok(false) => Ok(TemplateWrite::NotFound),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(TemplateWrite::NameTaken),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn template_from_row(row: &SqliteRow) -> Result<SessionTemplate> {
+    Ok(SessionTemplate {
+        name: row.try_get("name")?,
+        command: row.try_get("command")?,
+        args: serde_json::from_str(row.try_get("args")?)?,
+        cwd: row.try_get("cwd")?,
+        env: serde_json::from_str(row.try_get("env")?)?,
+        rows: row.try_get("rows")?,
+        cols: row.try_get("cols")?,
+        recording: row.try_get("recording")?,
+        idle_timeout_mins: row.try_get::<Option<i64>, _>("idle_timeout_mins")?.and_then(|m| u64::try_from(m).ok()),
+    })
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use crate::procfs;
+use crate::pty_manager::PtyManager;
+use crate::session::{now_millis, SessionRegistry};
+use crate::GlobalEvent;
+
+/// Chu kỳ kiểm tra foreground process của các session.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tiến trình đang giữ foreground của terminal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ForegroundProcess {
+    pub pid: u32,
+    /// Tên tiến trình (`comm`), ví dụ `cargo`.
+    pub name: String,
+    /// Dòng lệnh đầy đủ, ví dụ `cargo build --release`.
+    pub command: String,
+    /// Thời điểm tiến trình khởi động (unix ms).
+    pub started_at: u64,
+    /// Số giây đã chạy.
+    pub elapsed_secs: u64,
+    /// `false` khi foreground là chính shell (đang chờ lệnh ở prompt).
+    pub busy: bool,
+}
+
+/// Xác định foreground process của PTY qua `tcgetpgrp` và `/proc`.
+pub fn detect(pty: &PtyManager) -> Option<ForegroundProcess> {
+    let pgid = pty.foreground_pgid()?;
+    let stat = procfs::read_stat(pgid)?;
+    let started_at = procfs::start_time_millis(stat.starttime).unwrap_or(0);
+    Some(ForegroundProcess {
+        pid: pgid,
+        command: procfs::read_cmdline(pgid).unwrap_or_else(|| stat.comm.clone()),
+        name: stat.comm,
+        started_at,
+        elapsed_secs: now_millis().saturating_sub(started_at) / 1000,
+        busy: pty.pid() != Some(pgid),
+    })
+}
+
+/// Theo dõi thay đổi foreground process và phát `GlobalEvent::ForegroundChanged`.
+#[derive(Debug, Default)]
+pub struct ForegroundWatcher {
+    /// (pid, `started_at`) đã thấy lần trước cho mỗi session.
+    last: HashMap<String, Option<(u32, u64)>>,
+}
+
+impl ForegroundWatcher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Kiểm tra tất cả session một lần, trả về ID các session có thay đổi.
+    pub fn run_once(&mut self, registry: &SessionRegistry) -> Vec<String> {
+        let sessions = registry.all_sessions();
+        self.last.retain(|id, _| sessions.iter().any(|s| &s.id == id));
+
+        let mut changed = Vec::new();
+        for session in sessions {
+            let foreground = session.backend.foreground();
+            let key = foreground.as_ref().map(|f| (f.pid, f.started_at));
+            if self.last.insert(session.id.clone(), key) == Some(key) {
+                continue;
+            }
+            tracing::debug!(session_id = %session.id, ?foreground, "foreground process changed");
+            let _ = registry.global_tx().send(GlobalEvent::ForegroundChanged {
+                id: session.id.clone(),
+                foreground,
+            });
+            changed.push(session.id);
+        }
+        changed
+    }
+}
+
+pub fn spawn_foreground_watcher(registry: Arc<SessionRegistry>, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut watcher = ForegroundWatcher::new();
+        loop {
+            interval.tick().await;
+            watcher.run_once(&registry);
+        }
+    })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+    use crate::backend::SessionBackend;
+
+    async fn wait_for<F: Fn(&Option<ForegroundProcess>) -> bool>(pty: &dyn SessionBackend, check: F) -> Option<ForegroundProcess> {
+        for _ in 0..100 {
+            let fg = pty.foreground();
+            if check(&fg) {
+                return fg;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("foreground process did not reach expected state: {:?}", pty.foreground());
+    }
+
+    #[tokio::test]
+    async fn test_detects_foreground_command() {
+        let (tx, mut rx) = broadcast::channel(10);
+        let registry = SessionRegistry::new(tx);
+        let session = registry.create_session("fg".to_string()).unwrap();
+        let mut watcher = ForegroundWatcher::new();
+
+        // Shell ở prompt: không bận
+        let idle = wait_for(session.backend.as_ref(), |fg| fg.as_ref().is_some_and(|f| !f.busy)).await.unwrap();
+        assert_eq!(Some(idle.pid), session.backend.pid());
+        assert_eq!(watcher.run_once(&registry), vec!["fg".to_string()]);
+        assert!(matches!(rx.recv().await.unwrap(), GlobalEvent::ForegroundChanged { .. }));
+        assert!(watcher.run_once(&registry).is_empty(), "no event without a change");
+
+        session.backend.write(b"sleep 30\n").unwrap();
+        let busy = wait_for(session.backend.as_ref(), |fg| fg.as_ref().is_some_and(|f| f.busy)).await.unwrap();
+        assert_eq!(busy.name, "sleep");
+        assert_eq!(busy.command, "sleep 30");
+        assert!(busy.started_at > 0);
+
+        assert_eq!(watcher.run_once(&registry), vec!["fg".to_string()]);
+        match rx.recv().await.unwrap() {
+            GlobalEvent::ForegroundChanged { id, foreground } => {
+                assert_eq!(id, "fg");
+                assert_eq!(foreground.unwrap().name, "sleep");
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert!(session.info().foreground.unwrap().busy);
+
+        registry.terminate_session("fg");
+    }
+}
@@ -0,0 +1,177 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::AppState;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_HASH: &str = env!("BTERMINAL_GIT_HASH");
+
+/// Kết quả kiểm tra PTY được cache trong khoảng này để `/readyz` không fork tiến trình mỗi lần bị gọi.
+const PTY_PROBE_TTL: Duration = Duration::from_secs(10);
+/// Thời gian tối đa chờ tiến trình probe kết thúc.
+const PTY_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Kết quả probe gần nhất. Dùng mutex async để các request đồng thời chờ chung một lần probe.
+static PTY_PROBE: tokio::sync::Mutex<Option<(Instant, Result<(), String>)>> = tokio::sync::Mutex::const_new(None);
+
+#[derive(Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_hash: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct CheckResult {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    fn from_result(result: anyhow::Result<()>) -> Self {
+        match result {
+            Ok(()) => Self { ok: true, error: None },
+            Err(e) => Self { ok: false, error: Some(e.to_string()) },
+        }
+    }
+}
+
+impl From<&Result<(), String>> for CheckResult {
+    fn from(result: &Result<(), String>) -> Self {
+        Self { ok: result.is_ok(), error: result.clone().err() }
+    }
+}
+
+/// Kiểm tra khả năng spawn PTY, dùng lại kết quả cache nếu còn trong `PTY_PROBE_TTL`.
+async fn probe_pty() -> CheckResult {
+    let mut cached = PTY_PROBE.lock().await;
+    if let Some((checked_at, result)) = cached.as_ref()
+        && checked_at.elapsed() < PTY_PROBE_TTL
+    {
+        return CheckResult::from(result);
+    }
+
+    let result = tokio::task::spawn_blocking(|| crate::pty_manager::PtyManager::probe(PTY_PROBE_TIMEOUT))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r)
+        .map_err(|e| e.to_string());
+    let check = CheckResult::from(&result);
+    *cached = Some((Instant::now(), result));
+    check
+}
+
+#[derive(Serialize)]
+pub struct RegistryCheck {
+    pub ok: bool,
+    pub sessions: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub status: &'static str,
+    pub database: CheckResult,
+    pub pty: CheckResult,
+    pub registry: RegistryCheck,
+}
+
+/// `GET /healthz`: tiến trình còn sống và phục vụ được HTTP.
+pub async fn healthz() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// `GET /readyz`: kiểm tra database, khả năng spawn PTY và sức chứa của registry.
+/// Trả về `503` nếu có bất kỳ kiểm tra nào thất bại.
+pub async fn readyz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let database = CheckResult::from_result(state.db.ping().await);
+
+    let pty = probe_pty().await;
+
+    let sessions = state.registry.len();
+    let registry = RegistryCheck {
+        ok: !state.registry.is_at_capacity(),
+        sessions,
+        limit: state.registry.limits().max_sessions,
+    };
+
+    let ready = database.ok && pty.ok && registry.ok;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let body = Readiness {
+        status: if ready { "ready" } else { "not_ready" },
+        database,
+        pty,
+        registry,
+    };
+
+    (status, Json(body))
+}
+
+/// `GET /version`: thông tin build (version từ `Cargo.toml` và git hash).
+pub async fn version() -> Json<BuildInfo> {
+    Json(BuildInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: VERSION,
+        git_hash: GIT_HASH,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionRegistry;
+    use crate::db::Db;
+    use crate::config::Config;
+    use tokio::sync::broadcast;
+
+    async fn setup() -> Arc<AppState> {
+        let (tx, _) = broadcast::channel(10);
+        let registry = Arc::new(SessionRegistry::new(tx.clone()));
+        let db = Db::new("sqlite::memory:").await.unwrap();
+        Arc::new(AppState { registry, tx, db, config: Config::default() })
+    }
+
+    #[tokio::test]
+    async fn test_readyz_reports_all_checks() {
+        let state = setup().await;
+        let response = readyz(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "ready");
+        assert_eq!(json["database"]["ok"], true);
+        assert_eq!(json["pty"]["ok"], true);
+        assert_eq!(json["registry"]["sessions"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_readyz_fails_when_database_closed() {
+        let state = setup().await;
+        state.db.pool.close().await;
+
+        let response = readyz(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_pty_probe_is_cached() {
+        assert!(probe_pty().await.ok);
+        let first = PTY_PROBE.lock().await.as_ref().map(|(at, _)| *at);
+        assert!(probe_pty().await.ok);
+        let second = PTY_PROBE.lock().await.as_ref().map(|(at, _)| *at);
+        assert!(first.is_some());
+        assert_eq!(first, second, "probe within TTL should reuse the cached result");
+    }
+
+    #[tokio::test]
+    async fn test_version_matches_cargo_manifest() {
+        let Json(info) = version().await;
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_hash.is_empty());
+    }
+}
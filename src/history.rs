@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+/// Lịch sử output của session: ring buffer `limit` byte trong bộ nhớ; byte bị đẩy ra
+/// được ghi tiếp vào file spill trên đĩa nếu bật. Mỗi byte có một offset tăng dần từ 0.
+#[derive(Debug)]
+pub struct History {
+    memory: VecDeque<u8>,
+    limit: usize,
+    spill: Option<Spill>,
+    /// Offset của byte kế tiếp sẽ được ghi (tổng số byte đã ghi).
+    end: u64,
+}
+
+impl History {
+    #[must_use]
+    pub const fn new(limit: usize) -> Self {
+        Self { memory: VecDeque::new(), limit, spill: None, end: 0 }
+    }
+
+    /// Ghi phần bị đẩy khỏi bộ nhớ vào file trong `dir`, giữ tối đa `max_bytes` trên đĩa.
+    /// File bị xoá tên ngay khi tạo nên tự giải phóng khi session kết thúc (kể cả khi server crash).
+    ///
+    /// # Errors
+    /// Lỗi nếu không tạo được thư mục hoặc file.
+    pub fn spill_to(&mut self, dir: &Path, max_bytes: u64) -> io::Result<()> {
+        if max_bytes > 0 {
+            self.spill = Some(Spill::new(dir, max_bytes)?);
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        let overflow = (self.memory.len() + data.len()).saturating_sub(self.limit);
+        let from_memory = overflow.min(self.memory.len());
+        // Phần của `data` không vừa bộ nhớ (chunk lớn hơn `limit`)
+        let (direct, kept) = data.split_at(overflow - from_memory);
+        if let Some(spill) = self.spill.as_mut() {
+            let (front, back) = self.memory.as_slices();
+            let front_len = from_memory.min(front.len());
+            let evicted = [&front[..front_len], &back[..from_memory - front_len], direct];
+            if let Err(e) = evicted.iter().try_for_each(|chunk| spill.write(chunk)) {
+                tracing::error!(error = %e, dir = %spill.dir.display(), "history spill failed, disabling it");
+                self.spill = None;
+            }
+        }
+        self.memory.drain(..from_memory);
+        self.memory.extend(kept);
+        self.end += data.len() as u64;
+    }
+
+    /// Offset của byte kế tiếp sẽ được ghi.
+    #[must_use]
+    pub const fn end_offset(&self) -> u64 {
+        self.end
+    }
+
+    /// Offset của byte cũ nhất còn trong bộ nhớ.
+    #[must_use]
+    pub fn start_offset(&self) -> u64 {
+        self.end - self.memory.len() as u64
+    }
+
+    /// Các byte từ `offset` tới hiện tại; `None` nếu `offset` đã bị đẩy khỏi bộ nhớ hoặc lớn hơn `end_offset`.
+    #[must_use]
+    pub fn since(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset > self.end {
+            return None;
+        }
+        let skip = usize::try_from(offset.checked_sub(self.start_offset())?).ok()?;
+        Some(self.memory.range(skip..).copied().collect())
+    }
+
+    /// Số byte đang giữ trong bộ nhớ.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty()
+    }
+
+    /// Số byte đang giữ trên đĩa.
+    #[must_use]
+    pub fn spilled(&self) -> u64 {
+        self.spill.as_ref().map_or(0, Spill::len)
+    }
+
+    /// Phần lịch sử trong bộ nhớ (gửi lại cho client khi kết nối).
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.memory.iter().copied().collect()
+    }
+
+    /// Toàn bộ lịch sử, gồm cả phần đã spill ra đĩa.
+    #[must_use]
+    pub fn full(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        if let Some(spill) = &self.spill
+            && let Err(e) = spill.read_into(&mut data)
+        {
+            tracing::error!(error = %e, "failed to read history spill");
+            data.clear();
+        }
+        let (front, back) = self.memory.as_slices();
+        data.extend_from_slice(front);
+        data.extend_from_slice(back);
+        data
+    }
+}
+
+/// Hai segment nối tiếp, mỗi segment tối đa `max_bytes / 2`: khi segment hiện tại đầy,
+/// segment cũ bị bỏ, tổng dung lượng trên đĩa không vượt quá `max_bytes`.
+#[derive(Debug)]
+struct Spill {
+    dir: PathBuf,
+    segment_size: u64,
+    older: Option<Segment>,
+    current: Segment,
+}
+
+#[derive(Debug)]
+struct Segment {
+    file: File,
+    len: u64,
+}
+
+impl Segment {
+    fn create(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(format!("{}.spill", uuid::Uuid::new_v4().simple()));
+        let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(Self { file, len: 0 })
+    }
+}
+
+impl Spill {
+    fn new(dir: &Path, max_bytes: u64) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            segment_size: (max_bytes / 2).max(1),
+            older: None,
+            current: Segment::create(dir)?,
+        })
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            if self.current.len >= self.segment_size {
+                let fresh = Segment::create(&self.dir)?;
+                self.older = Some(std::mem::replace(&mut self.current, fresh));
+            }
+            let room = usize::try_from(self.segment_size - self.current.len).unwrap_or(usize::MAX);
+            let (chunk, rest) = data.split_at(room.min(data.len()));
+            self.current.file.write_all_at(chunk, self.current.len)?;
+            self.current.len += chunk.len() as u64;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    fn read_into(&self, out: &mut Vec<u8>) -> io::Result<()> {
+        for segment in self.older.iter().chain(std::iter::once(&self.current)) {
+            let start = out.len();
+            let len = usize::try_from(segment.len).map_err(io::Error::other)?;
+            out.resize(start + len, 0);
+            segment.file.read_exact_at(&mut out[start..], 0)?;
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        self.older.as_ref().map_or(0, |s| s.len) + self.current.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spill_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("bterm-spill-{}", uuid::Uuid::new_v4().simple()))
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_latest_bytes() {
+        let mut history = History::new(8);
+        assert!(history.is_empty());
+        history.push(b"0123");
+        history.push(b"456789");
+        assert_eq!(history.snapshot(), b"23456789");
+        history.push(b"abcdefghijkl");
+        assert_eq!(history.snapshot(), b"efghijkl");
+        assert_eq!(history.len(), 8);
+        assert_eq!(history.full(), history.snapshot());
+        assert_eq!(history.spilled(), 0);
+
+        let mut empty = History::new(0);
+        empty.push(b"data");
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_offsets_and_since() {
+        let mut history = History::new(8);
+        assert_eq!((history.start_offset(), history.end_offset()), (0, 0));
+        assert_eq!(history.since(0), Some(Vec::new()));
+        history.push(b"01234");
+        history.push(b"56789");
+        assert_eq!((history.start_offset(), history.end_offset()), (2, 10));
+        assert_eq!(history.since(7), Some(b"789".to_vec()));
+        assert_eq!(history.since(2), Some(b"23456789".to_vec()));
+        assert_eq!(history.since(10), Some(Vec::new()));
+        // Đã bị đẩy khỏi bộ nhớ, hoặc offset chưa tồn tại
+        assert_eq!(history.since(1), None);
+        assert_eq!(history.since(11), None);
+    }
+
+    #[test]
+    fn test_spill_keeps_evicted_bytes_on_disk() {
+        let dir = spill_dir();
+        let mut history = History::new(4);
+        history.spill_to(&dir, 10).unwrap();
+        // Tên file bị xoá ngay, không để lại gì trong thư mục
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        history.push(b"abcdef");
+        history.push(b"gh");
+        assert_eq!(history.snapshot(), b"efgh");
+        assert_eq!(history.spilled(), 4);
+        assert_eq!(history.full(), b"abcdefgh");
+
+        // Vượt `max_bytes`: segment cũ nhất bị bỏ
+        history.push(b"ijklmnopqrstuvwxyz");
+        assert_eq!(history.snapshot(), b"wxyz");
+        assert_eq!(history.spilled(), 7);
+        assert_eq!(history.full(), b"pqrstuvwxyz");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_spill_disabled_with_zero_size() {
+        let dir = spill_dir();
+        let mut history = History::new(2);
+        history.spill_to(&dir, 0).unwrap();
+        history.push(b"abc");
+        assert_eq!(history.full(), b"bc");
+        assert!(!dir.exists());
+    }
+}
@@ -7,14 +7,32 @@
 #![allow(clippy::must_use_candidate)]
 
 pub mod pty_manager;
+pub mod backend;
 pub mod session;
 pub mod ws;
 pub mod api;
 pub mod db;
 pub mod auth;
+pub mod telemetry;
+pub mod config;
+pub mod metrics;
+pub mod health;
+pub mod reaper;
+pub mod osc;
+pub mod procfs;
+pub mod foreground;
+pub mod stats;
+pub mod sandbox;
+pub mod ssh;
+pub mod recording;
+pub mod templates;
+pub mod ansi;
+pub mod scrollback;
+pub mod history;
+pub mod output;
 
 use axum::{
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
 use std::sync::Arc;
@@ -37,16 +55,31 @@ pub struct Assets;
 pub enum GlobalEvent {
     SessionCreated(String),
     SessionDeleted(String),
+    SessionReaped { id: String, reason: reaper::ReapReason },
+    SessionUpdated(Box<session::SessionInfo>),
+    /// Title/cwd do shell báo qua OSC 0/2/7 thay đổi.
+    TerminalStateChanged { id: String, title: Option<String>, cwd: Option<String> },
+    /// Foreground process của terminal thay đổi (chạy lệnh mới hoặc quay về prompt).
+    ForegroundChanged { id: String, foreground: Option<foreground::ForegroundProcess> },
+    /// Số liệu tài nguyên định kỳ của mọi session.
+    SessionStats(Vec<stats::SessionUsage>),
 }
 
 pub struct AppState {
     pub registry: Arc<SessionRegistry>,
     pub tx: broadcast::Sender<GlobalEvent>,
     pub db: db::Db,
+    pub config: config::Config,
 }
 
-pub fn create_app(tx: broadcast::Sender<GlobalEvent>, registry: Arc<SessionRegistry>, db: db::Db) -> Router {
-    let state = Arc::new(AppState { registry, tx, db });
+pub fn create_app(
+    tx: broadcast::Sender<GlobalEvent>,
+    registry: Arc<SessionRegistry>,
+    db: db::Db,
+    config: config::Config,
+) -> Router {
+    metrics::init();
+    let state = Arc::new(AppState { registry, tx, db, config });
 
     let session_store = MemoryStore::default();
     let session_layer = SessionManagerLayer::new(session_store)
@@ -57,8 +90,19 @@ pub fn create_app(tx: broadcast::Sender<GlobalEvent>, registry: Arc<SessionRegis
         .nest("/api", Router::new()
             .route("/sessions", get(api::list_sessions))
             .route("/sessions", post(api::create_session))
-            .route("/sessions/{id}", axum::routing::delete(api::delete_session))
+            .route("/sessions/{id}", get(api::get_session).patch(api::update_session).delete(api::delete_session))
+            .route("/sessions/{id}/signal", post(api::signal_session))
+            .route("/sessions/{id}/clone", post(api::clone_session))
+            .route("/sessions/{id}/search", get(api::search_session))
+            .route("/sessions/{id}/scrollback", get(api::session_scrollback))
+            .route("/sessions/{id}/stats", get(api::session_stats))
+            .route("/sessions/{id}/processes", get(api::list_processes))
+            .route("/sessions/{id}/processes/{pid}/signal", post(api::signal_process))
+            .route("/templates", get(api::list_templates).post(api::create_template))
+            .route("/templates/{name}", get(api::get_template).put(api::update_template).delete(api::delete_template))
+            .route("/ssh/hosts", get(api::list_ssh_hosts))
             .route("/events", get(api::events_handler))
+            .route("/users/{id}/unix-user", put(api::set_unix_user))
         )
         .route("/ws/{session_id}", get(ws::ws_handler))
         .layer(axum::middleware::from_fn_with_state(state.clone(), auth::require_auth));
@@ -68,9 +112,17 @@ pub fn create_app(tx: broadcast::Sender<GlobalEvent>, registry: Arc<SessionRegis
     Router::new()
         .merge(protected_routes)
         .nest("/api/auth", auth_routes)
+        .route("/metrics", get(metrics::metrics_handler))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/version", get(health::version))
         .layer(session_layer)
         .fallback(static_handler)
+        .layer(axum::middleware::from_fn(metrics::track_http))
         .with_state(state)
+        .layer(telemetry::propagate_request_id_layer())
+        .layer(telemetry::trace_layer())
+        .layer(telemetry::set_request_id_layer())
         .layer(CorsLayer::permissive())
 }
 
@@ -118,7 +170,7 @@ mod tests {
         let registry = Arc::new(SessionRegistry::new(tx.clone()));
         // Setup in-memory DB for tests
         let db = db::Db::new("sqlite::memory:").await.unwrap();
-        create_app(tx, registry, db)
+        create_app(tx, registry, db, config::Config::default())
     }
 
     #[tokio::test]
@@ -160,4 +212,72 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
+
+    #[tokio::test]
+    async fn test_request_id_header_generated_and_propagated() {
+        let app = setup_app().await;
+
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.headers().contains_key(telemetry::REQUEST_ID_HEADER));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header(telemetry::REQUEST_ID_HEADER, "req-42")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[telemetry::REQUEST_ID_HEADER], "req-42");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_exposes_prometheus_text() {
+        let app = setup_app().await;
+
+        let response = app
+            .oneshot(Request::builder().uri("/metrics").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+        let text = String::from_utf8_lossy(&body);
+        assert!(text.contains("bterminal_active_sessions"));
+        assert!(text.contains("bterminal_sse_subscribers"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_requires_token_when_configured() {
+        let (tx, _) = broadcast::channel(10);
+        let registry = Arc::new(SessionRegistry::new(tx.clone()));
+        let db = db::Db::new("sqlite::memory:").await.unwrap();
+        let config = config::Config { metrics_token: Some("s3cret".to_string()), ..Default::default() };
+        let app = create_app(tx, registry, db, config);
+
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/metrics").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics")
+                    .header(header::AUTHORIZATION, "Bearer s3cret")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
@@ -1,14 +1,19 @@
 use std::sync::Arc;
-use bterminal::{create_app, session::SessionRegistry, db::Db};
+use bterminal::{create_app, config::{Config, IdlePolicy, SessionLimits}, session::SessionRegistry, db::Db};
 
 #[cfg(not(tarpaulin_include))]
 #[tokio::main]
 async fn main() {
+    bterminal::telemetry::init();
+
     let (tx, _rx) = tokio::sync::broadcast::channel(100);
-    let registry = Arc::new(SessionRegistry::new(tx.clone()));
+    let registry = Arc::new(SessionRegistry::with_limits(tx.clone(), SessionLimits::from_env()));
+    let _reaper = bterminal::reaper::spawn_reaper(registry.clone(), IdlePolicy::from_env());
+    let _stats = bterminal::stats::spawn_stats_sampler(registry.clone(), bterminal::config::stats_interval_from_env());
+    let _foreground = bterminal::foreground::spawn_foreground_watcher(registry.clone(), bterminal::foreground::POLL_INTERVAL);
     
     let db_url = std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:bterminal.db".to_string());
-    println!("Database URL: {}", db_url);
+    tracing::info!(%db_url, "opening database");
     
     let db = Db::new(&db_url).await.expect("Failed to initialize database");
 
@@ -16,16 +21,16 @@ async fn main() {
     // In production this should be done via a setup script or CLI
     let admin_username = "admin";
     if db.get_user_by_username(admin_username).await.unwrap().is_none() {
-        println!("Creating default admin user...");
+        tracing::info!("creating default admin user");
         let password_hash = bterminal::auth::hash_password("admin").expect("Failed to hash password");
         let must_change = std::env::var("SKIP_ADMIN_PWD_CHANGE").is_err();
         match db.create_user_with_pwd_policy(admin_username, &password_hash, "admin", must_change).await {
-            Ok(_) => println!("Default admin user created (admin/admin), must_change={}", must_change),
-            Err(e) => println!("Failed to create admin user: {}", e),
+            Ok(_) => tracing::info!(must_change, "default admin user created (admin/admin)"),
+            Err(e) => tracing::error!(error = %e, "failed to create admin user"),
         }
     }
 
-    let app = create_app(tx, registry, db);
+    let app = create_app(tx, registry, db, Config::from_env());
 
     let listener = bind_listener().await;
     let local_addr = listener.local_addr().unwrap();
@@ -0,0 +1,190 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
+use subtle::ConstantTimeEq;
+use crate::AppState;
+
+pub static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+pub static ACTIVE_SESSIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("bterminal_active_sessions", "Number of live terminal sessions").unwrap())
+});
+
+pub static WS_CLIENTS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register(
+        IntGaugeVec::new(
+            Opts::new("bterminal_ws_clients", "Connected WebSocket clients per session"),
+            &["session_id"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static PTY_BYTES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("bterminal_pty_bytes_total", "Bytes written to (in) and read from (out) PTYs"),
+            &["direction"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static BROADCAST_LAG_EVENTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("bterminal_broadcast_lag_events_total", "RecvError::Lagged occurrences on PTY broadcast channels"),
+            &["consumer"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static BROADCAST_LAGGED_MESSAGES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("bterminal_broadcast_lagged_messages_total", "Messages skipped by lagging broadcast receivers"),
+            &["consumer"],
+        )
+        .unwrap(),
+    )
+});
+
+pub static LOGINS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(Opts::new("bterminal_logins_total", "Login attempts by result"), &["result"]).unwrap(),
+    )
+});
+
+pub static SSE_SUBSCRIBERS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new("bterminal_sse_subscribers", "Connected SSE (/api/events) subscribers").unwrap())
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new("bterminal_http_request_duration_seconds", "HTTP request latency"),
+            &["method", "path", "status"],
+        )
+        .unwrap(),
+    )
+});
+
+/// Đăng ký trước tất cả metric để `/metrics` luôn liệt kê đủ (kể cả khi chưa có giá trị).
+pub fn init() {
+    LazyLock::force(&ACTIVE_SESSIONS);
+    LazyLock::force(&WS_CLIENTS);
+    LazyLock::force(&PTY_BYTES);
+    LazyLock::force(&BROADCAST_LAG_EVENTS);
+    LazyLock::force(&BROADCAST_LAGGED_MESSAGES);
+    LazyLock::force(&LOGINS);
+    LazyLock::force(&SSE_SUBSCRIBERS);
+    LazyLock::force(&HTTP_REQUEST_DURATION);
+}
+
+fn register<M: prometheus::core::Collector + Clone + 'static>(metric: M) -> M {
+    REGISTRY.register(Box::new(metric.clone())).unwrap();
+    metric
+}
+
+/// Ghi nhận một lần receiver broadcast bị lag (`consumer`: `ws`, `monitor`, ...).
+pub fn record_lag(consumer: &str, skipped: u64) {
+    BROADCAST_LAG_EVENTS.with_label_values(&[consumer]).inc();
+    BROADCAST_LAGGED_MESSAGES.with_label_values(&[consumer]).inc_by(skipped);
+}
+
+/// Guard tăng gauge khi tạo và giảm khi drop, dùng cho các kết nối dài (SSE, WS).
+pub struct GaugeGuard(IntGauge);
+
+impl GaugeGuard {
+    pub fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Middleware đo latency theo route đã match (tránh bùng nổ label với đường dẫn tĩnh).
+pub async fn track_http(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |p| p.as_str().to_string());
+
+    let response = next.run(request).await;
+
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[method.as_str(), path.as_str(), response.status().as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+/// `GET /metrics` theo định dạng text của Prometheus.
+/// Nếu cấu hình `METRICS_TOKEN`, request phải gửi `Authorization: Bearer <token>`.
+pub async fn metrics_handler(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if let Some(token) = &state.config.metrics_token {
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|v| bool::from(v.as_bytes().ct_eq(token.as_bytes())));
+        if !authorized {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    // Các gauge theo session được tính lại tại thời điểm scrape để không giữ label của session đã xóa.
+    let sessions = state.registry.all_sessions();
+    ACTIVE_SESSIONS.set(i64::try_from(sessions.len()).unwrap_or(i64::MAX));
+    WS_CLIENTS.reset();
+    for session in &sessions {
+        WS_CLIENTS
+            .with_label_values(&[session.id.as_str()])
+            .set(i64::try_from(session.client_count()).unwrap_or(i64::MAX));
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        tracing::error!(error = %e, "failed to encode metrics");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    ([(header::CONTENT_TYPE, TextEncoder::new().format_type().to_string())], buffer).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_lag_increments_counters() {
+        let before = BROADCAST_LAG_EVENTS.with_label_values(&["test"]).get();
+        let skipped_before = BROADCAST_LAGGED_MESSAGES.with_label_values(&["test"]).get();
+        record_lag("test", 7);
+        assert_eq!(BROADCAST_LAG_EVENTS.with_label_values(&["test"]).get(), before + 1);
+        assert_eq!(BROADCAST_LAGGED_MESSAGES.with_label_values(&["test"]).get(), skipped_before + 7);
+    }
+
+    #[test]
+    fn test_gauge_guard() {
+        let gauge = IntGauge::new("test_gauge", "test").unwrap();
+        {
+            let _guard = GaugeGuard::new(gauge.clone());
+            assert_eq!(gauge.get(), 1);
+        }
+        assert_eq!(gauge.get(), 0);
+    }
+}
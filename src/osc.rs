@@ -0,0 +1,223 @@
+/// Độ dài tối đa của một chuỗi OSC được giữ lại; dài hơn thì bỏ qua.
+const MAX_OSC_LEN: usize = 4096;
+/// Độ dài tối đa của title sau khi làm sạch.
+const MAX_TITLE_LEN: usize = 256;
+
+/// Title và thư mục làm việc mới nhất mà shell báo qua OSC.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TerminalState {
+    pub title: Option<String>,
+    pub cwd: Option<String>,
+}
+
+impl TerminalState {
+    /// Áp dụng một sự kiện, trả về `true` nếu giá trị thay đổi.
+    pub fn apply(&mut self, event: OscEvent) -> bool {
+        let (slot, value) = match event {
+            OscEvent::Title(title) => (&mut self.title, title),
+            OscEvent::Cwd(cwd) => (&mut self.cwd, cwd),
+        };
+        if slot.as_deref() == Some(value.as_str()) {
+            return false;
+        }
+        *slot = Some(value);
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OscEvent {
+    /// OSC 0 / OSC 2: window title.
+    Title(String),
+    /// OSC 7: `file://host/path`, đã giải mã thành đường dẫn.
+    Cwd(String),
+}
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// Parser OSC dạng streaming: một chuỗi có thể bị cắt giữa nhiều lần đọc PTY.
+/// Output không bị thay đổi, parser chỉ quan sát.
+#[derive(Debug, Default)]
+pub struct OscParser {
+    state: State,
+    buf: Vec<u8>,
+    overflow: bool,
+}
+
+impl OscParser {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bỏ chuỗi đang dở (ví dụ khi bị mất dữ liệu do lag).
+    pub fn reset(&mut self) {
+        self.state = State::Ground;
+        self.buf.clear();
+        self.overflow = false;
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Vec<OscEvent> {
+        let mut events = Vec::new();
+        for &b in data {
+            match self.state {
+                State::Ground => {
+                    if b == 0x1b {
+                        self.state = State::Escape;
+                    }
+                }
+                State::Escape => {
+                    self.state = match b {
+                        b']' => {
+                            self.buf.clear();
+                            self.overflow = false;
+                            State::Osc
+                        }
+                        0x1b => State::Escape,
+                        _ => State::Ground,
+                    };
+                }
+                State::Osc => match b {
+                    // BEL kết thúc OSC
+                    0x07 => self.finish(&mut events),
+                    0x1b => self.state = State::OscEscape,
+                    // CAN/SUB hủy chuỗi
+                    0x18 | 0x1a => self.state = State::Ground,
+                    _ => self.push(b),
+                },
+                State::OscEscape => {
+                    if b == b'\\' {
+                        // ST (ESC \)
+                        self.finish(&mut events);
+                    } else {
+                        // ESC khác: chuỗi OSC bị bỏ dở, xử lý như escape mới
+                        self.buf.clear();
+                        self.state = State::Escape;
+                        if b == b']' {
+                            self.overflow = false;
+                            self.state = State::Osc;
+                        }
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.buf.len() < MAX_OSC_LEN {
+            self.buf.push(b);
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    fn finish(&mut self, events: &mut Vec<OscEvent>) {
+        self.state = State::Ground;
+        if !self.overflow
+            && let Some(event) = parse_osc(&self.buf)
+        {
+            events.push(event);
+        }
+        self.buf.clear();
+    }
+}
+
+fn parse_osc(payload: &[u8]) -> Option<OscEvent> {
+    let payload = std::str::from_utf8(payload).ok()?;
+    let (code, value) = payload.split_once(';')?;
+    match code {
+        "0" | "2" => Some(OscEvent::Title(sanitize_title(value))),
+        "7" => parse_file_url(value).map(OscEvent::Cwd),
+        _ => None,
+    }
+}
+
+fn sanitize_title(value: &str) -> String {
+    value.chars().filter(|c| !c.is_control()).take(MAX_TITLE_LEN).collect()
+}
+
+/// `file://host/path%20with%20space` -> `/path with space`.
+fn parse_file_url(value: &str) -> Option<String> {
+    let rest = value.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let bytes = percent_decode(path.as_bytes())?;
+    String::from_utf8(bytes).ok()
+}
+
+fn percent_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' {
+            let hex = std::str::from_utf8(input.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(input[i]);
+            i += 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_with_bel_and_st() {
+        let mut parser = OscParser::new();
+        assert_eq!(parser.feed(b"\x1b]0;vim main.rs\x07"), vec![OscEvent::Title("vim main.rs".to_string())]);
+        assert_eq!(parser.feed(b"ls\r\n\x1b]2;htop\x1b\\$ "), vec![OscEvent::Title("htop".to_string())]);
+        // OSC 1 (icon name) và các mã khác bị bỏ qua
+        assert!(parser.feed(b"\x1b]1;icon\x07\x1b]52;c;aGVsbG8=\x07").is_empty());
+    }
+
+    #[test]
+    fn test_cwd_from_osc7() {
+        let mut parser = OscParser::new();
+        let events = parser.feed(b"\x1b]7;file://myhost/home/user/my%20repo\x1b\\");
+        assert_eq!(events, vec![OscEvent::Cwd("/home/user/my repo".to_string())]);
+        assert!(parser.feed(b"\x1b]7;not-a-url\x07").is_empty());
+    }
+
+    #[test]
+    fn test_sequence_split_across_chunks() {
+        let mut parser = OscParser::new();
+        assert!(parser.feed(b"output\x1b").is_empty());
+        assert!(parser.feed(b"]2;cargo ").is_empty());
+        assert!(parser.feed(b"build\x1b").is_empty());
+        assert_eq!(parser.feed(b"\\"), vec![OscEvent::Title("cargo build".to_string())]);
+    }
+
+    #[test]
+    fn test_oversized_and_reset() {
+        let mut parser = OscParser::new();
+        let mut long = b"\x1b]2;".to_vec();
+        long.extend(std::iter::repeat_n(b'a', MAX_OSC_LEN + 10));
+        long.push(0x07);
+        assert!(parser.feed(&long).is_empty());
+
+        assert!(parser.feed(b"\x1b]2;partial").is_empty());
+        parser.reset();
+        assert!(parser.feed(b"\x07").is_empty());
+    }
+
+    #[test]
+    fn test_terminal_state_reports_changes() {
+        let mut state = TerminalState::default();
+        assert!(state.apply(OscEvent::Title("a".to_string())));
+        assert!(!state.apply(OscEvent::Title("a".to_string())));
+        assert!(state.apply(OscEvent::Cwd("/tmp".to_string())));
+        assert_eq!(state.title.as_deref(), Some("a"));
+        assert_eq!(state.cwd.as_deref(), Some("/tmp"));
+    }
+}
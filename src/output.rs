@@ -0,0 +1,372 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Notify};
+use crate::session::Output;
+
+/// Kích thước tối đa của một message sau khi gộp.
+pub const MAX_BATCH_SIZE: usize = 64 * 1024;
+/// Số lần đọc chờ gộp; reader dừng đọc khi hàng đợi đầy (ví dụ lúc output bị tạm dừng).
+const PENDING_READS: usize = 16;
+
+/// Cách backend đọc output vào broadcast channel của session.
+#[derive(Debug, Clone, Default)]
+pub struct ReaderOptions {
+    /// Gộp các lần đọc trong khoảng này thành một message (`Duration::ZERO` = không chờ).
+    pub coalesce: Duration,
+    /// Reader ngừng đọc khi cổng đang tạm dừng.
+    pub flow: Arc<FlowControl>,
+}
+
+/// Cổng tạm dừng đọc output của backend khi mọi client đều tụt lại.
+#[derive(Debug, Default)]
+pub struct FlowControl {
+    state: Mutex<FlowState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct FlowState {
+    paused: bool,
+    /// Session đã kết thúc: không bao giờ tạm dừng nữa để reader đọc được tới EOF.
+    closed: bool,
+}
+
+impl FlowControl {
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.paused && !state.closed
+    }
+
+    fn set_paused(&self, paused: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.paused == paused {
+            return;
+        }
+        state.paused = paused;
+        drop(state);
+        tracing::debug!(paused, "output flow changed");
+        self.changed.notify_all();
+    }
+
+    /// Mở cổng vĩnh viễn.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+
+    /// Chặn thread hiện tại tới khi cổng mở.
+    pub fn wait(&self) {
+        let state = self.state.lock().unwrap();
+        drop(self.changed.wait_while(state, |s| s.paused && !s.closed).unwrap());
+    }
+}
+
+/// Đọc `reader` trên thread riêng và đẩy vào `tx`: các lần đọc tới trong vòng `options.coalesce`
+/// được gộp thành một message (tối đa `MAX_BATCH_SIZE`). Gửi `Vec` rỗng khi gặp EOF hoặc lỗi đọc.
+pub fn pump(mut reader: Box<dyn Read + Send>, tx: broadcast::Sender<Vec<u8>>, options: ReaderOptions) {
+    let span = tracing::Span::current();
+    let (chunks_tx, chunks) = mpsc::sync_channel::<Vec<u8>>(PENDING_READS);
+    let reader_span = span.clone();
+    thread::spawn(move || {
+        let _enter = reader_span.enter();
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = reader.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            crate::metrics::PTY_BYTES.with_label_values(&["out"]).inc_by(n as u64);
+            if chunks_tx.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+        tracing::debug!("PTY reader thread exiting");
+    });
+    thread::spawn(move || {
+        let _enter = span.enter();
+        while let Ok(mut batch) = chunks.recv() {
+            let deadline = Instant::now() + options.coalesce;
+            while batch.len() < MAX_BATCH_SIZE {
+                match chunks.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(chunk) => batch.extend_from_slice(&chunk),
+                    Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            if let Err(e) = tx.send(batch) {
+                tracing::trace!(error = %e, "broadcast error (expected if no listeners)");
+            }
+            options.flow.wait();
+        }
+        // Send an empty vector to signal termination to subscribers
+        let _ = tx.send(Vec::new());
+    });
+}
+
+/// Phát output của session vào hàng đợi riêng của từng WebSocket client. Mỗi hàng đợi giữ tối đa
+/// `limit` byte output; client chậm hơn thế bị bỏ hàng đợi và gửi lại từ lịch sử (`Received::Overflowed`).
+#[derive(Debug)]
+pub struct Fanout {
+    clients: Mutex<HashMap<uuid::Uuid, Arc<ClientQueue>>>,
+    limit: usize,
+    /// Tạm dừng đọc backend khi mọi client đều đầy quá nửa hàng đợi.
+    pause_when_behind: bool,
+    flow: Arc<FlowControl>,
+}
+
+#[derive(Debug, Default)]
+struct ClientQueue {
+    state: Mutex<QueueState>,
+    ready: Notify,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    items: VecDeque<Output>,
+    /// Tổng số byte `Output::Data` đang chờ.
+    bytes: usize,
+    overflowed: bool,
+    /// Số message output bị bỏ từ lần đồng bộ lại trước.
+    dropped: u64,
+}
+
+impl Fanout {
+    #[must_use]
+    pub fn new(limit: usize, pause_when_behind: bool) -> Self {
+        Self { clients: Mutex::default(), limit, pause_when_behind, flow: Arc::default() }
+    }
+
+    /// Cổng tạm dừng truyền cho reader của backend.
+    #[must_use]
+    pub const fn flow(&self) -> &Arc<FlowControl> {
+        &self.flow
+    }
+
+    /// Đăng ký client mới; hàng đợi bị huỷ khi `Subscription` bị drop.
+    #[must_use]
+    pub fn subscribe(self: &Arc<Self>) -> Subscription {
+        let id = uuid::Uuid::new_v4();
+        let queue = Arc::new(ClientQueue::default());
+        self.clients.lock().unwrap().insert(id, queue.clone());
+        self.update_flow();
+        Subscription { fanout: self.clone(), id, queue }
+    }
+
+    pub fn publish(&self, output: &Output) {
+        for queue in self.clients.lock().unwrap().values() {
+            queue.push(output.clone(), self.limit);
+        }
+        self.update_flow();
+    }
+
+    /// Mở cổng vĩnh viễn khi session kết thúc để reader không bị giữ lại.
+    pub fn close(&self) {
+        self.flow.close();
+    }
+
+    /// Tạm dừng khi mọi client đều có từ nửa hàng đợi trở lên, tiếp tục khi có client còn dưới một phần tư.
+    fn update_flow(&self) {
+        if !self.pause_when_behind {
+            return;
+        }
+        let clients = self.clients.lock().unwrap();
+        let pending: Vec<usize> = clients.values().map(|queue| queue.state.lock().unwrap().bytes).collect();
+        drop(clients);
+        if self.flow.is_paused() {
+            if pending.iter().all(|bytes| *bytes > self.limit / 4) && !pending.is_empty() {
+                return;
+            }
+            self.flow.set_paused(false);
+        } else if !pending.is_empty() && pending.iter().all(|bytes| *bytes >= self.limit / 2) {
+            self.flow.set_paused(true);
+        }
+    }
+}
+
+impl ClientQueue {
+    fn push(&self, output: Output, limit: usize) {
+        let mut state = self.state.lock().unwrap();
+        if let Output::Data { data, .. } = &output {
+            if state.overflowed {
+                state.dropped += 1;
+                return;
+            }
+            if state.bytes + data.len() > limit {
+                // Bỏ output đang chờ; client sẽ được gửi lại từ lịch sử
+                let before = state.items.len();
+                state.items.retain(|item| !matches!(item, Output::Data { .. }));
+                state.dropped += (before - state.items.len()) as u64 + 1;
+                state.bytes = 0;
+                state.overflowed = true;
+                drop(state);
+                self.ready.notify_one();
+                return;
+            }
+            state.bytes += data.len();
+        }
+        state.items.push_back(output);
+        drop(state);
+        self.ready.notify_one();
+    }
+}
+
+/// Kết quả nhận từ hàng đợi của client.
+#[derive(Debug)]
+pub enum Received {
+    Output(Output),
+    /// Hàng đợi đã tràn và output bị bỏ (kèm số message bị bỏ): client phải đồng bộ lại từ lịch sử
+    /// rồi gọi `Subscription::resume`.
+    Overflowed(u64),
+}
+
+/// Hàng đợi output của một client.
+#[derive(Debug)]
+pub struct Subscription {
+    fanout: Arc<Fanout>,
+    id: uuid::Uuid,
+    queue: Arc<ClientQueue>,
+}
+
+impl Subscription {
+    pub async fn recv(&self) -> Received {
+        loop {
+            let item = {
+                let mut state = self.queue.state.lock().unwrap();
+                if state.overflowed {
+                    return Received::Overflowed(state.dropped);
+                }
+                let item = state.items.pop_front();
+                if let Some(Output::Data { data, .. }) = &item {
+                    state.bytes -= data.len();
+                }
+                item
+            };
+            if let Some(item) = item {
+                self.fanout.update_flow();
+                return Received::Output(item);
+            }
+            self.queue.ready.notified().await;
+        }
+    }
+
+    /// Nhận output tiếp sau khi tràn. Gọi khi đang giữ khóa lịch sử, cùng lúc lấy dữ liệu đồng bộ lại,
+    /// để không mất hay lặp byte nào.
+    pub fn resume(&self) {
+        let mut state = self.queue.state.lock().unwrap();
+        state.overflowed = false;
+        state.dropped = 0;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.fanout.clients.lock().unwrap().remove(&self.id);
+        self.fanout.update_flow();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(offset: u64, bytes: &[u8]) -> Output {
+        Output::Data { offset, data: bytes.to_vec().into() }
+    }
+
+    async fn next_data(sub: &Subscription) -> (u64, Vec<u8>) {
+        match sub.recv().await {
+            Received::Output(Output::Data { offset, data }) => (offset, data.to_vec()),
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_pump_coalesces_reads() {
+        let (tx, mut rx) = broadcast::channel(10);
+        let options = ReaderOptions { coalesce: Duration::from_millis(200), ..ReaderOptions::default() };
+        let (reader, mut writer) = std::os::unix::net::UnixStream::pair().unwrap();
+        pump(Box::new(reader), tx, options);
+        for chunk in [&b"one "[..], b"two ", b"three"] {
+            std::io::Write::write_all(&mut writer, chunk).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(writer);
+        assert_eq!(rx.blocking_recv().unwrap(), b"one two three");
+        assert!(rx.blocking_recv().unwrap().is_empty(), "EOF is signalled with an empty message");
+    }
+
+    #[test]
+    fn test_flow_control_blocks_until_resumed() {
+        let flow = Arc::new(FlowControl::default());
+        flow.set_paused(true);
+        let waiter = {
+            let flow = flow.clone();
+            thread::spawn(move || flow.wait())
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+        flow.set_paused(false);
+        waiter.join().unwrap();
+
+        // Sau khi đóng, cổng không bao giờ chặn nữa
+        flow.set_paused(true);
+        flow.close();
+        assert!(!flow.is_paused());
+        flow.wait();
+    }
+
+    #[tokio::test]
+    async fn test_fanout_overflow_drops_to_resync() {
+        let fanout = Arc::new(Fanout::new(8, false));
+        let fast = fanout.subscribe();
+        let slow = fanout.subscribe();
+
+        fanout.publish(&data(0, b"abcd"));
+        assert_eq!(next_data(&fast).await, (0, b"abcd".to_vec()));
+        fanout.publish(&data(4, b"efgh"));
+        fanout.publish(&Output::Control(r#"{"type":"SetSize"}"#.to_string()));
+        fanout.publish(&data(8, b"ij"));
+        fanout.publish(&data(10, b"kl"));
+
+        // `fast` còn 4 byte chờ, không tràn
+        assert_eq!(next_data(&fast).await, (4, b"efgh".to_vec()));
+        assert!(matches!(fast.recv().await, Received::Output(Output::Control(_))));
+        assert_eq!(next_data(&fast).await, (8, b"ij".to_vec()));
+
+        // `slow` vượt 8 byte: bỏ output đang chờ và output tới sau đó (4 message), giữ tin nhắn điều khiển
+        assert!(matches!(slow.recv().await, Received::Overflowed(4)));
+        slow.resume();
+        assert!(matches!(slow.recv().await, Received::Output(Output::Control(_))));
+        fanout.publish(&data(12, b"mn"));
+        assert_eq!(next_data(&slow).await, (12, b"mn".to_vec()));
+
+        drop(slow);
+        assert_eq!(fanout.clients.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fanout_pauses_when_all_clients_behind() {
+        let fanout = Arc::new(Fanout::new(8, true));
+        let a = fanout.subscribe();
+        let b = fanout.subscribe();
+
+        fanout.publish(&data(0, b"abcd"));
+        assert!(fanout.flow().is_paused(), "both clients have half a queue pending");
+
+        // Một client đã bắt kịp: tiếp tục đọc
+        next_data(&a).await;
+        assert!(!fanout.flow().is_paused());
+
+        fanout.publish(&data(4, b"efgh"));
+        assert!(fanout.flow().is_paused());
+        // Client chậm ngắt kết nối: chỉ còn client vừa nhận output
+        drop(b);
+        next_data(&a).await;
+        assert!(!fanout.flow().is_paused());
+        drop(a);
+        assert!(!fanout.flow().is_paused());
+    }
+}
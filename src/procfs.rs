@@ -0,0 +1,222 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Các trường cần dùng trong `/proc/<pid>/stat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcStat {
+    pub pid: u32,
+    /// Tên tiến trình (`comm`), tối đa 15 ký tự.
+    pub comm: String,
+    pub state: char,
+    pub ppid: u32,
+    pub pgrp: u32,
+    /// Session ID (pid của session leader, thường là shell).
+    pub session: u32,
+    /// Thời gian CPU user/system (clock ticks).
+    pub utime: u64,
+    pub stime: u64,
+    pub num_threads: u64,
+    /// Thời điểm khởi động tính từ lúc boot (clock ticks).
+    pub starttime: u64,
+    /// Resident set size (pages).
+    pub rss_pages: u64,
+}
+
+/// Parse nội dung `/proc/<pid>/stat`. `comm` có thể chứa khoảng trắng và `)`,
+/// nên các trường sau được tách từ dấu `)` cuối cùng.
+pub fn parse_stat(content: &str) -> Option<ProcStat> {
+    let open = content.find('(')?;
+    let close = content.rfind(')')?;
+    let pid = content[..open].trim().parse().ok()?;
+    let comm = content.get(open + 1..close)?.to_string();
+    let fields: Vec<&str> = content.get(close + 1..)?.split_whitespace().collect();
+    // fields[0] là trường thứ 3 (state) theo proc(5)
+    let field = |n: usize| fields.get(n - 3).copied();
+    let num = |n: usize| field(n).and_then(|v| v.parse::<u64>().ok());
+    Some(ProcStat {
+        pid,
+        comm,
+        state: field(3)?.chars().next()?,
+        ppid: field(4)?.parse().ok()?,
+        pgrp: field(5)?.parse().ok()?,
+        session: field(6)?.parse().ok()?,
+        utime: num(14)?,
+        stime: num(15)?,
+        num_threads: num(20)?,
+        starttime: num(22)?,
+        rss_pages: num(24)?,
+    })
+}
+
+pub fn read_stat(pid: u32) -> Option<ProcStat> {
+    parse_stat(&std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?)
+}
+
+/// Thư mục làm việc hiện tại của tiến trình (`/proc/<pid>/cwd`), nếu thư mục còn tồn tại.
+pub fn cwd(pid: u32) -> Option<String> {
+    let path = std::fs::read_link(format!("/proc/{pid}/cwd")).ok()?;
+    path.is_dir().then(|| path.to_str().map(str::to_string)).flatten()
+}
+
+/// PID của tất cả tiến trình đang chạy.
+pub fn list_pids() -> Vec<u32> {
+    std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|e| e.file_name().to_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `root` và mọi tiến trình con cháu của nó (theo `ppid`), sắp xếp theo pid.
+pub fn descendants(root: u32) -> Vec<ProcStat> {
+    let all: Vec<ProcStat> = list_pids().into_iter().filter_map(read_stat).collect();
+    let mut included = std::collections::HashSet::from([root]);
+    // Lặp tới khi không thêm được tiến trình nào (cây có thể không theo thứ tự pid)
+    loop {
+        let before = included.len();
+        for stat in &all {
+            if included.contains(&stat.ppid) {
+                included.insert(stat.pid);
+            }
+        }
+        if included.len() == before {
+            break;
+        }
+    }
+    let mut tree: Vec<ProcStat> = all.into_iter().filter(|s| included.contains(&s.pid)).collect();
+    tree.sort_by_key(|s| s.pid);
+    tree
+}
+
+/// Tất cả tiến trình thuộc session `sid` (theo `setsid`).
+pub fn session_members(sid: u32) -> Vec<ProcStat> {
+    list_pids()
+        .into_iter()
+        .filter_map(read_stat)
+        .filter(|stat| stat.session == sid)
+        .collect()
+}
+
+/// Số file descriptor đang mở của tiến trình (`None` nếu không có quyền đọc).
+pub fn count_fds(pid: u32) -> Option<usize> {
+    Some(std::fs::read_dir(format!("/proc/{pid}/fd")).ok()?.count())
+}
+
+/// Kích thước một page bộ nhớ (byte).
+pub fn page_size() -> u64 {
+    static PAGE: OnceLock<u64> = OnceLock::new();
+    *PAGE.get_or_init(|| {
+        #[cfg(unix)]
+        {
+            let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+            u64::try_from(size).ok().filter(|&s| s > 0).unwrap_or(4096)
+        }
+        #[cfg(not(unix))]
+        {
+            4096
+        }
+    })
+}
+
+/// Tổng tài nguyên của mọi tiến trình trong session `sid` tại một thời điểm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionTotals {
+    /// Tổng `utime + stime` (clock ticks).
+    pub cpu_ticks: u64,
+    pub rss_bytes: u64,
+    pub processes: usize,
+    pub threads: u64,
+    pub open_fds: usize,
+}
+
+pub fn session_totals(sid: u32) -> SessionTotals {
+    let page = page_size();
+    session_members(sid).iter().fold(SessionTotals::default(), |mut acc, stat| {
+        acc.cpu_ticks += stat.utime + stat.stime;
+        acc.rss_bytes += stat.rss_pages * page;
+        acc.processes += 1;
+        acc.threads += stat.num_threads;
+        acc.open_fds += count_fds(stat.pid).unwrap_or(0);
+        acc
+    })
+}
+
+/// Dòng lệnh đầy đủ (`/proc/<pid>/cmdline`), các tham số nối bằng khoảng trắng.
+pub fn read_cmdline(pid: u32) -> Option<String> {
+    let raw = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    let args: Vec<String> = raw
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    (!args.is_empty()).then(|| args.join(" "))
+}
+
+/// Số clock tick mỗi giây (`sysconf(_SC_CLK_TCK)`).
+pub fn clock_ticks() -> u64 {
+    static TICKS: OnceLock<u64> = OnceLock::new();
+    *TICKS.get_or_init(|| {
+        #[cfg(unix)]
+        {
+            let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+            u64::try_from(ticks).ok().filter(|&t| t > 0).unwrap_or(100)
+        }
+        #[cfg(not(unix))]
+        {
+            100
+        }
+    })
+}
+
+/// Thời điểm boot (unix giây), lấy từ dòng `btime` của `/proc/stat`.
+fn boot_time() -> Option<u64> {
+    static BOOT: OnceLock<Option<u64>> = OnceLock::new();
+    *BOOT.get_or_init(|| {
+        let content = std::fs::read_to_string(Path::new("/proc/stat")).ok()?;
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix("btime "))
+            .and_then(|v| v.trim().parse().ok())
+    })
+}
+
+/// Đổi `starttime` (ticks kể từ boot) sang unix ms.
+pub fn start_time_millis(starttime: u64) -> Option<u64> {
+    Some(boot_time()? * 1000 + starttime * 1000 / clock_ticks())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat_with_odd_comm() {
+        let line = "4242 (my (weird) cmd) S 1 4242 4242 34816 4242 4194560 100 0 0 0 \
+                    25 7 0 0 20 0 3 0 123456 10000000 512 18446744073709551615";
+        let stat = parse_stat(line).unwrap();
+        assert_eq!(stat.pid, 4242);
+        assert_eq!(stat.comm, "my (weird) cmd");
+        assert_eq!(stat.state, 'S');
+        assert_eq!((stat.ppid, stat.pgrp, stat.session), (1, 4242, 4242));
+        assert_eq!((stat.utime, stat.stime), (25, 7));
+        assert_eq!(stat.num_threads, 3);
+        assert_eq!(stat.starttime, 123_456);
+        assert_eq!(stat.rss_pages, 512);
+        assert!(parse_stat("garbage").is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_self() {
+        let pid = std::process::id();
+        let stat = read_stat(pid).unwrap();
+        assert_eq!(stat.pid, pid);
+        assert!(read_cmdline(pid).is_some());
+        let started = start_time_millis(stat.starttime).unwrap();
+        assert!(started <= crate::session::now_millis());
+        assert!(count_fds(pid).unwrap() > 0);
+    }
+}
@@ -1,37 +1,207 @@
 use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem, MasterPty, Child};
 use tokio::sync::broadcast;
+use serde::{Deserialize, Serialize};
+use crate::output::ReaderOptions;
+
+/// Tín hiệu có thể gửi tới session qua API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SessionSignal {
+    #[serde(rename = "SIGINT", alias = "INT")]
+    Int,
+    #[serde(rename = "SIGTERM", alias = "TERM")]
+    Term,
+    #[serde(rename = "SIGHUP", alias = "HUP")]
+    Hup,
+    #[serde(rename = "SIGKILL", alias = "KILL")]
+    Kill,
+    #[serde(rename = "SIGSTOP", alias = "STOP")]
+    Stop,
+    #[serde(rename = "SIGCONT", alias = "CONT")]
+    Cont,
+}
+
+#[cfg(unix)]
+impl From<SessionSignal> for nix::sys::signal::Signal {
+    fn from(signal: SessionSignal) -> Self {
+        match signal {
+            SessionSignal::Int => Self::SIGINT,
+            SessionSignal::Term => Self::SIGTERM,
+            SessionSignal::Hup => Self::SIGHUP,
+            SessionSignal::Kill => Self::SIGKILL,
+            SessionSignal::Stop => Self::SIGSTOP,
+            SessionSignal::Cont => Self::SIGCONT,
+        }
+    }
+}
+
+/// Tham số spawn shell cho một session.
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOptions {
+    /// Dùng để đặt tên cgroup (`session-<id>`).
+    pub session_id: Option<String>,
+    pub limits: crate::config::ShellLimits,
+    /// Chạy shell dưới tài khoản Unix này (server cần quyền root).
+    pub run_as: Option<String>,
+    /// Lọc môi trường kế thừa và chế độ login shell.
+    pub environment: crate::config::ShellEnvironment,
+    /// Biến đặt thêm cho shell, ví dụ `BTERMINAL_SESSION_ID`.
+    pub env: Vec<(String, String)>,
+    /// Chương trình chạy thay cho shell; `None` = shell của user.
+    pub command: Option<String>,
+    /// Tham số của `command`.
+    pub args: Vec<String>,
+    /// Thư mục làm việc; `None` = thư mục home.
+    pub cwd: Option<String>,
+    /// Kích thước terminal ban đầu (rows, cols); mặc định 24x80.
+    pub size: Option<(u16, u16)>,
+}
+
+/// Khoảng tối thiểu giữa hai mốc tính CPU%, tránh số liệu nhiễu khi bị gọi dồn dập.
+const MIN_CPU_WINDOW: Duration = Duration::from_millis(500);
+
+/// Mức dùng tài nguyên của mọi tiến trình trong session của shell.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    /// CPU% (100 = một core) kể từ lần lấy mẫu trước; `0` ở lần đầu.
+    pub cpu_percent: f64,
+    pub rss_bytes: u64,
+    pub processes: usize,
+    pub threads: u64,
+    pub open_fds: usize,
+    /// Thời điểm lấy mẫu (unix ms).
+    pub sampled_at: u64,
+}
+
+/// Một tiến trình trong cây tiến trình của session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProcessEntry {
+    pub pid: u32,
+    pub ppid: u32,
+    pub pgrp: u32,
+    /// Tên tiến trình (`comm`).
+    pub name: String,
+    /// Dòng lệnh đầy đủ (rỗng với kernel thread/zombie).
+    pub command: String,
+    /// Trạng thái theo proc(5): `R`, `S`, `T`, `Z`...
+    pub state: char,
+    /// Thời điểm khởi động (unix ms).
+    pub started_at: u64,
+}
+
+/// Phạm vi nhận tín hiệu.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignalTarget {
+    /// Process group đang giữ foreground của terminal (giống gõ ^C).
+    #[default]
+    Foreground,
+    /// Mọi process group trong session của shell, kể cả job chạy nền và chính shell.
+    Session,
+}
 
 pub struct PtyManager {
     master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
     writer: Arc<Mutex<Box<dyn Write + Send>>>,
     reader: Mutex<Option<Box<dyn Read + Send>>>,
     child: Mutex<Option<Box<dyn Child + Send>>>,
+    /// PID của shell (leader của process group).
+    pid: Option<u32>,
+    /// Lệnh đã spawn (shell, hoặc `command` kèm tham số).
+    command: String,
+    /// Thư mục làm việc lúc spawn.
+    cwd: Option<String>,
+    /// Exit code của shell, được ghi lại khi tiến trình kết thúc.
+    exit_status: Mutex<Option<u32>>,
+    /// Mốc (thời điểm, tổng CPU ticks) để tính CPU% giữa hai lần lấy mẫu.
+    cpu_baseline: Mutex<Option<(Instant, u64)>>,
+    /// cgroup của session; drop sau khi shell đã bị kill trong `Drop`.
+    cgroup: Option<crate::sandbox::SessionCgroup>,
 }
 
 impl PtyManager {
         #[must_use]
         pub fn new() -> Self {
+            Self::spawn(&SpawnOptions::default()).unwrap()
+        }
+
+        /// Mở PTY và spawn shell với các giới hạn trong `options`.
+        ///
+        /// # Errors
+        /// Lỗi nếu không mở được PTY, không tạo được cgroup hoặc không spawn được shell.
+        pub fn spawn(options: &SpawnOptions) -> anyhow::Result<Self> {
             let pty_system = NativePtySystem::default();
+            let (rows, cols) = options.size.unwrap_or((24, 80));
             let pair = pty_system
                 .openpty(PtySize {
-                    rows: 24,
-                    cols: 80,
+                    rows,
+                    cols,
                     pixel_width: 0,
                     pixel_height: 0,
-                })
-                .unwrap();
+                })?;
     
+            #[cfg(unix)]
+            let account = options.run_as.as_deref().map(crate::sandbox::UnixAccount::lookup).transpose()?;
+            #[cfg(not(unix))]
+            anyhow::ensure!(options.run_as.is_none(), "running shells as another user is not supported on this platform");
+
             let shell = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
-            let mut cmd = CommandBuilder::new(shell);
-            cmd.env("TERM", "xterm-256color");
-            cmd.env("COLORTERM", "truecolor");
-            cmd.env("LANG", "C.UTF-8");
-    
-            let child = pair.slave.spawn_command(cmd).unwrap();
+            // Giống portable_pty: dùng HOME làm thư mục làm việc
+            let cwd = std::env::var("HOME").ok();
+            // Tài khoản được map dùng login shell và home của chính nó
+            #[cfg(unix)]
+            let (shell, cwd) = match &account {
+                Some(account) if !account.shell.as_os_str().is_empty() => {
+                    (account.shell.to_string_lossy().into_owned(), Some(account.home.to_string_lossy().into_owned()))
+                }
+                Some(account) => (shell, Some(account.home.to_string_lossy().into_owned())),
+                None => (shell, cwd),
+            };
+            let home = cwd.clone();
+            let cwd = match &options.cwd {
+                Some(dir) => {
+                    anyhow::ensure!(std::path::Path::new(dir).is_dir(), "working directory {dir} does not exist");
+                    Some(dir.clone())
+                }
+                None => cwd,
+            };
+
+            let cgroup = match (&options.limits.cgroup, &options.session_id) {
+                (Some(limits), Some(id)) => Some(crate::sandbox::SessionCgroup::create(limits, &format!("session-{id}"))?),
+                _ => None,
+            };
+            #[cfg(unix)]
+            let child = spawn_shell(pair.master.as_ref(), &shell, home.as_deref(), cwd.as_deref(), options, cgroup.as_ref(), account.as_ref())?;
+            #[cfg(not(unix))]
+            let child = {
+                let mut cmd = CommandBuilder::new(options.command.as_deref().unwrap_or(&shell));
+                cmd.args(&options.args);
+                if options.environment.login && options.command.is_none() {
+                    cmd.arg("-l");
+                }
+                if let Some(dir) = &cwd {
+                    cmd.cwd(dir);
+                }
+                cmd.env_clear();
+                for (key, value) in options.environment.inherited(std::env::vars_os()) {
+                    cmd.env(key, value);
+                }
+                for (key, value) in &options.env {
+                    cmd.env(key, value);
+                }
+                cmd.env("TERM", "xterm-256color");
+                cmd.env("COLORTERM", "truecolor");
+                cmd.env("LANG", "C.UTF-8");
+                pair.slave.spawn_command(cmd)?
+            };
+            drop(pair.slave);
+            let child_pid = child.process_id();
+            if let Some(cgroup) = &cgroup {
+                tracing::debug!(path = %cgroup.path().display(), "shell placed in session cgroup");
+            }
             
             #[cfg(unix)]
             {
@@ -55,17 +225,206 @@ impl PtyManager {
                 }
             }
     
-            let writer = pair.master.take_writer().unwrap();
-            let reader = pair.master.try_clone_reader().unwrap();
+            let writer = pair.master.take_writer()?;
+            let reader = pair.master.try_clone_reader()?;
             let master = pair.master;
     
-            Self {
+            Ok(Self {
                 master: Arc::new(Mutex::new(master)),
                 writer: Arc::new(Mutex::new(writer)),
                 reader: Mutex::new(Some(reader)),
                 child: Mutex::new(Some(child)),
+                pid: child_pid,
+                command: options.command.as_ref().map_or(shell, |program| {
+                    std::iter::once(program).chain(&options.args).cloned().collect::<Vec<_>>().join(" ")
+                }),
+                cwd,
+                exit_status: Mutex::new(None),
+                cpu_baseline: Mutex::new(None),
+                cgroup,
+            })
+        }
+
+    pub const fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn cwd(&self) -> Option<&str> {
+        self.cwd.as_deref()
+    }
+
+    /// Thư mục cgroup v2 của session, nếu được cấu hình.
+    pub fn cgroup_path(&self) -> Option<&std::path::Path> {
+        self.cgroup.as_ref().map(crate::sandbox::SessionCgroup::path)
+    }
+
+    /// Kích thước hiện tại của PTY (rows, cols).
+    pub fn size(&self) -> Option<(u16, u16)> {
+        let size = self.master.lock().unwrap().get_size().ok()?;
+        Some((size.rows, size.cols))
+    }
+
+    /// Process group đang ở foreground của terminal (`tcgetpgrp` trên master).
+    pub fn foreground_pgid(&self) -> Option<u32> {
+        #[cfg(unix)]
+        {
+            let pgid = self.master.lock().unwrap().process_group_leader()?;
+            u32::try_from(pgid).ok().filter(|&p| p > 0)
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// Exit code của shell nếu nó đã kết thúc (kiểm tra không chặn).
+    pub fn exit_status(&self) -> Option<u32> {
+        let mut status = self.exit_status.lock().unwrap();
+        if status.is_none()
+            && let Some(child) = self.child.lock().unwrap().as_mut()
+            && let Ok(Some(exit)) = child.try_wait()
+        {
+            *status = Some(exit.exit_code());
+        }
+        *status
+    }
+
+    /// Kiểm tra khả năng cấp phát PTY và spawn tiến trình (dùng cho `/readyz`).
+    /// Spawn `true` trên PTY mới rồi chờ nó kết thúc trong tối đa `timeout`.
+    pub fn probe(timeout: Duration) -> anyhow::Result<()> {
+        let pair = NativePtySystem::default().openpty(PtySize::default())?;
+        let mut child = pair.slave.spawn_command(CommandBuilder::new("true"))?;
+        let deadline = std::time::Instant::now() + timeout;
+        while child.try_wait()?.is_none() {
+            if std::time::Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                anyhow::bail!("PTY probe did not exit within {timeout:?}");
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    }
+
+    /// Lấy mẫu CPU/RSS/số tiến trình/fd từ `/proc` cho toàn bộ session của shell.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn sample_usage(&self) -> Option<ResourceUsage> {
+        let totals = crate::procfs::session_totals(self.pid?);
+        if totals.processes == 0 {
+            return None;
+        }
+        let now = Instant::now();
+        let mut baseline = self.cpu_baseline.lock().unwrap();
+        let cpu_percent = match *baseline {
+            Some((at, ticks)) if now > at => {
+                let used = totals.cpu_ticks.saturating_sub(ticks) as f64 / crate::procfs::clock_ticks() as f64;
+                (used / now.duration_since(at).as_secs_f64() * 1000.0).round() / 10.0
             }
+            _ => 0.0,
+        };
+        if baseline.is_none_or(|(at, _)| now.duration_since(at) >= MIN_CPU_WINDOW) {
+            *baseline = Some((now, totals.cpu_ticks));
         }
+        drop(baseline);
+        Some(ResourceUsage {
+            cpu_percent,
+            rss_bytes: totals.rss_bytes,
+            processes: totals.processes,
+            threads: totals.threads,
+            open_fds: totals.open_fds,
+            sampled_at: crate::session::now_millis(),
+        })
+    }
+
+    /// Shell và mọi tiến trình con cháu của nó, đọc từ `/proc`.
+    pub fn process_tree(&self) -> Vec<ProcessEntry> {
+        let Some(root) = self.pid else {
+            return Vec::new();
+        };
+        crate::procfs::descendants(root)
+            .into_iter()
+            .map(|stat| ProcessEntry {
+                pid: stat.pid,
+                ppid: stat.ppid,
+                pgrp: stat.pgrp,
+                command: crate::procfs::read_cmdline(stat.pid).unwrap_or_default(),
+                name: stat.comm,
+                state: stat.state,
+                started_at: crate::procfs::start_time_millis(stat.starttime).unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// Gửi `signal` tới một tiến trình, chỉ khi nó thuộc cây tiến trình của session.
+    ///
+    /// # Errors
+    /// Lỗi nếu `pid` không thuộc session hoặc không gửi được tín hiệu.
+    pub fn signal_pid(&self, pid: u32, signal: SessionSignal) -> anyhow::Result<()> {
+        let root = self.pid.ok_or_else(|| anyhow::anyhow!("shell process has exited"))?;
+        if !crate::procfs::descendants(root).iter().any(|p| p.pid == pid) {
+            anyhow::bail!("process {pid} does not belong to this session");
+        }
+        #[cfg(unix)]
+        {
+            use nix::unistd::Pid;
+            nix::sys::signal::kill(Pid::from_raw(pid.cast_signed()), nix::sys::signal::Signal::from(signal))?;
+            Ok(())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = signal;
+            anyhow::bail!("signals are not supported on this platform")
+        }
+    }
+
+    /// Gửi `signal` tới `target`, trả về các process group đã nhận tín hiệu.
+    ///
+    /// # Errors
+    /// Lỗi nếu shell đã kết thúc hoặc không gửi được tới process group nào.
+    pub fn signal(&self, signal: SessionSignal, target: SignalTarget) -> anyhow::Result<Vec<u32>> {
+        let Some(shell) = self.pid.filter(|_| self.child.lock().unwrap().is_some()) else {
+            anyhow::bail!("shell process has exited");
+        };
+        let groups = match target {
+            SignalTarget::Foreground => vec![self.foreground_pgid().unwrap_or(shell)],
+            SignalTarget::Session => {
+                let mut groups: Vec<u32> = crate::procfs::session_members(shell).iter().map(|p| p.pgrp).collect();
+                groups.push(shell);
+                groups.sort_unstable();
+                groups.dedup();
+                groups
+            }
+        };
+        Self::signal_groups(signal, groups)
+    }
+
+    #[cfg(unix)]
+    fn signal_groups(signal: SessionSignal, groups: Vec<u32>) -> anyhow::Result<Vec<u32>> {
+        use nix::sys::signal::killpg;
+        use nix::unistd::Pid;
+
+        let mut delivered = Vec::with_capacity(groups.len());
+        let mut last_error = None;
+        for pgid in groups {
+            match killpg(Pid::from_raw(pgid.cast_signed()), nix::sys::signal::Signal::from(signal)) {
+                Ok(()) => delivered.push(pgid),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if delivered.is_empty() => Err(e.into()),
+            _ => Ok(delivered),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn signal_groups(_signal: SessionSignal, _groups: Vec<u32>) -> anyhow::Result<Vec<u32>> {
+        anyhow::bail!("signals are not supported on this platform")
+    }
 
     /// Shutdown the PTY and kill the associated process group.
     pub fn shutdown(&self) {
@@ -80,27 +439,21 @@ impl PtyManager {
                 let _ = kill(Pid::from_raw(-pid.cast_signed()), Signal::SIGKILL);
             }
             // Reap the child process to avoid zombies.
-            let _ = child.wait();
+            if let Ok(exit) = child.wait() {
+                self.exit_status.lock().unwrap().get_or_insert(exit.exit_code());
+            }
         }
     }
 
     pub fn start_reader(&self, tx: broadcast::Sender<Vec<u8>>) {
-        let mut reader_opt = self.reader.lock().unwrap();
-        if let Some(mut reader) = reader_opt.take() {
-            thread::spawn(move || {
-                let mut buf = [0u8; 1024];
-                while let Ok(n) = reader.read(&mut buf) {
-                    if n == 0 {
-                        break;
-                    }
-                    if let Err(e) = tx.send(buf[..n].to_vec()) {
-                        println!("Broadcast error (expected if no listeners): {e}");
-                    }
-                }
-                println!("PTY Reader thread exiting.");
-                // Send an empty vector to signal termination to subscribers
-                let _ = tx.send(Vec::new());
-            });
+        self.start_reader_with(tx, ReaderOptions::default());
+    }
+
+    /// Như `start_reader`, gộp các lần đọc theo `options.coalesce` và ngừng đọc khi `options.flow` tạm dừng.
+    pub fn start_reader_with(&self, tx: broadcast::Sender<Vec<u8>>, options: ReaderOptions) {
+        let reader = self.reader.lock().unwrap().take();
+        if let Some(reader) = reader {
+            crate::output::pump(reader, tx, options);
         }
     }
 
@@ -111,6 +464,7 @@ impl PtyManager {
         writer.write_all(data)?;
         writer.flush()?;
         drop(writer);
+        crate::metrics::PTY_BYTES.with_label_values(&["in"]).inc_by(data.len() as u64);
         Ok(())
     }
 
@@ -127,6 +481,109 @@ impl PtyManager {
     }
 }
 
+/// Spawn shell trên slave của PTY bằng `std::process::Command`, để có thể áp dụng
+/// rlimit và cgroup trong `pre_exec` (`portable_pty` không cho thêm hook).
+#[cfg(unix)]
+fn spawn_shell(
+    master: &dyn MasterPty,
+    shell: &str,
+    home: Option<&str>,
+    cwd: Option<&str>,
+    options: &SpawnOptions,
+    cgroup: Option<&crate::sandbox::SessionCgroup>,
+    account: Option<&crate::sandbox::UnixAccount>,
+) -> anyhow::Result<Box<dyn Child + Send + Sync>> {
+    use std::io::Write as _;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::CommandExt;
+
+    let tty_name = master.tty_name().ok_or_else(|| anyhow::anyhow!("PTY has no slave device name"))?;
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(&tty_name)?;
+
+    let mut cmd = std::process::Command::new(options.command.as_deref().unwrap_or(shell));
+    cmd.args(&options.args);
+    if options.environment.login && options.command.is_none() {
+        // Quy ước của login(1): argv0 bắt đầu bằng `-`
+        let name = std::path::Path::new(shell).file_name().map_or_else(|| shell.into(), |n| n.to_string_lossy());
+        cmd.arg0(format!("-{name}"));
+    }
+    cmd.env_clear();
+    cmd.envs(options.environment.inherited(std::env::vars_os()));
+    cmd.env("TERM", "xterm-256color");
+    cmd.env("COLORTERM", "truecolor");
+    cmd.env("LANG", "C.UTF-8");
+    if let Some(dir) = cwd.filter(|d| std::path::Path::new(d).is_dir()) {
+        cmd.current_dir(dir);
+    }
+    if options.environment.clean && account.is_none() {
+        // Môi trường sạch vẫn cần các biến cơ bản mà shell và chương trình mong đợi
+        if let Some(home) = home {
+            cmd.env("HOME", home);
+        }
+        if let Ok(Some(user)) = nix::unistd::User::from_uid(nix::unistd::geteuid()) {
+            cmd.env("USER", &user.name);
+            cmd.env("LOGNAME", &user.name);
+        }
+        cmd.env("SHELL", shell);
+    }
+    cmd.envs(options.env.iter().map(|(key, value)| (key, value)));
+    if let Some(account) = account {
+        cmd.env("HOME", &account.home);
+        cmd.env("USER", &account.name);
+        cmd.env("LOGNAME", &account.name);
+        cmd.env("SHELL", shell);
+        // Giống login(1): terminal thuộc về user của shell
+        std::os::unix::fs::fchown(&tty, Some(account.uid), Some(account.gid))?;
+    }
+    cmd.stdin(tty.try_clone()?).stdout(tty.try_clone()?).stderr(tty);
+    let switch_user = account.filter(|a| !a.is_current()).cloned();
+
+    let rlimits = crate::sandbox::rlimits(&options.limits);
+    let mut procs = cgroup.map(crate::sandbox::SessionCgroup::open_procs).transpose()?;
+
+    // Chỉ dùng các lời gọi async-signal-safe sau fork, không cấp phát.
+    unsafe {
+        cmd.pre_exec(move || {
+            for signo in [libc::SIGCHLD, libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM, libc::SIGALRM] {
+                libc::signal(signo, libc::SIG_DFL);
+            }
+            let empty_set: libc::sigset_t = std::mem::zeroed();
+            libc::sigprocmask(libc::SIG_SETMASK, &raw const empty_set, std::ptr::null_mut());
+
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            #[allow(clippy::cast_lossless)]
+            if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // Ghi "0" vào cgroup.procs: chuyển chính tiến trình này vào cgroup trước exec
+            if let Some(procs) = procs.as_mut() {
+                procs.write_all(b"0")?;
+            }
+            crate::sandbox::apply_rlimits(&rlimits)?;
+            if let Some(account) = &switch_user {
+                account.drop_privileges()?;
+            }
+            // Không để shell thừa hưởng fd của server
+            #[cfg(all(target_os = "linux", target_env = "gnu"))]
+            let _ = libc::close_range(3, libc::c_uint::MAX, libc::CLOSE_RANGE_CLOEXEC.cast_signed());
+            Ok(())
+        });
+    }
+
+    let mut child = cmd.spawn()?;
+    child.stdin.take();
+    child.stdout.take();
+    child.stderr.take();
+    tracing::trace!(tty = %tty_name.display(), "shell spawned");
+    Ok(Box::new(child))
+}
+
 impl Drop for PtyManager {
     fn drop(&mut self) {
         self.shutdown();
@@ -288,4 +745,97 @@ mod tests {
             }
         }
     }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_spawn_applies_rlimits() {
+        let limits = crate::config::ShellLimits { open_files: Some(64), core_bytes: Some(0), ..Default::default() };
+        let pty = PtyManager::spawn(&SpawnOptions { limits, ..Default::default() }).unwrap();
+        assert!(pty.cgroup_path().is_none());
+        let (tx, mut rx) = broadcast::channel(100);
+        pty.start_reader(tx);
+        pty.write(b"echo LIM:$(ulimit -n):$(ulimit -c)\n").unwrap();
+
+        let mut output = String::new();
+        let result = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Ok(data) = rx.recv().await {
+                output.push_str(&String::from_utf8_lossy(&data));
+                if output.contains("LIM:64:0") {
+                    return;
+                }
+            }
+        })
+        .await;
+        assert!(result.is_ok(), "limits not applied, output: {output}");
+        // Shell vẫn là session leader với PTY là controlling terminal
+        assert_eq!(pty.foreground_pgid(), pty.pid());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_clean_login_environment() {
+        let environment = crate::config::ShellEnvironment {
+            clean: true,
+            allowlist: vec!["PATH".to_string()],
+            login: true,
+        };
+        let env = vec![("BTERMINAL_SESSION_ID".to_string(), "s1".to_string())];
+        let pty = PtyManager::spawn(&SpawnOptions { environment, env, ..Default::default() }).unwrap();
+        let (tx, mut rx) = broadcast::channel(100);
+        pty.start_reader(tx);
+        // cargo đặt CARGO_MANIFEST_DIR cho tiến trình test, shell không được thấy nó
+        pty.write(b"echo ENV:$BTERMINAL_SESSION_ID:${CARGO_MANIFEST_DIR:-none}:$0\n").unwrap();
+
+        let mut output = String::new();
+        let result = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Ok(data) = rx.recv().await {
+                output.push_str(&String::from_utf8_lossy(&data));
+                if output.contains("ENV:s1:none:-") {
+                    return;
+                }
+            }
+        })
+        .await;
+        assert!(result.is_ok(), "unexpected environment, output: {output}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_spawn_as_unix_user() {
+        assert!(PtyManager::spawn(&SpawnOptions { run_as: Some("no-such-user-bterm".to_string()), ..Default::default() }).is_err());
+        if !nix::unistd::geteuid().is_root() {
+            return;
+        }
+        let account = crate::sandbox::UnixAccount::lookup("nobody").unwrap();
+        let pty = PtyManager::spawn(&SpawnOptions { run_as: Some("nobody".to_string()), ..Default::default() }).unwrap();
+        let pid = pty.pid().unwrap();
+        // Tiến trình (kể cả khi nologin đã thoát và thành zombie) mang uid/gid của tài khoản
+        let ids = |prefix: &str| -> Vec<u32> {
+            let status = std::fs::read_to_string(format!("/proc/{pid}/status")).unwrap();
+            let line = status.lines().find(|l| l.starts_with(prefix)).unwrap().to_string();
+            line[prefix.len()..].split_whitespace().map(|v| v.parse().unwrap()).collect()
+        };
+        for _ in 0..100 {
+            if ids("Uid:")[0] != 0 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(ids("Uid:"), vec![account.uid; 4]);
+        assert_eq!(ids("Gid:"), vec![account.gid; 4]);
+        assert_eq!(pty.cwd(), Some(account.home.to_string_lossy().as_ref()));
+    }
+
+    #[test]
+    fn test_spawn_fails_when_cgroup_unavailable() {
+        let limits = crate::config::ShellLimits {
+            cgroup: Some(crate::config::CgroupLimits {
+                parent: std::path::PathBuf::from("/nonexistent/bterminal.slice"),
+                memory_max: Some(1 << 30),
+                pids_max: None,
+            }),
+            ..Default::default()
+        };
+        let options = SpawnOptions { session_id: Some("cg".to_string()), limits, ..Default::default() };
+        assert!(PtyManager::spawn(&options).is_err());
+    }
 }
\ No newline at end of file
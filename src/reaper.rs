@@ -0,0 +1,254 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use crate::config::IdlePolicy;
+use crate::session::{now_millis, Session, SessionActivity, SessionRegistry};
+use crate::GlobalEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReapReason {
+    /// Không có input/output trong `idle_timeout`.
+    Idle,
+    /// Không có client nào gắn vào trong `detached_timeout`.
+    Detached,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Keep,
+    Warn { reason: ReapReason, remaining: Duration },
+    Reap(ReapReason),
+}
+
+/// Payload của tin nhắn `IdleWarning` gửi tới các client đang gắn vào.
+#[derive(Debug, Serialize)]
+struct IdleWarning {
+    reason: ReapReason,
+    seconds: u64,
+}
+
+const fn elapsed(now: u64, since: u64) -> Duration {
+    Duration::from_millis(now.saturating_sub(since))
+}
+
+/// Đánh giá một session theo chính sách idle tại thời điểm `now` (unix ms).
+pub fn evaluate(activity: &SessionActivity, clients: usize, policy: &IdlePolicy, now: u64) -> Decision {
+    let mut warn = None;
+
+    if let Some(timeout) = policy.idle_timeout {
+        let idle = elapsed(now, activity.last_activity());
+        if idle >= timeout {
+            return Decision::Reap(ReapReason::Idle);
+        }
+        if clients > 0 && idle + policy.warning >= timeout {
+            warn = Some(Decision::Warn { reason: ReapReason::Idle, remaining: timeout.saturating_sub(idle) });
+        }
+    }
+
+    if clients == 0
+        && let Some(timeout) = policy.detached_timeout
+        && elapsed(now, activity.detached_since()) >= timeout
+    {
+        return Decision::Reap(ReapReason::Detached);
+    }
+
+    warn.unwrap_or(Decision::Keep)
+}
+
+fn warn_clients(session: &Session, reason: ReapReason, remaining: Duration) {
+    session.send_control("IdleWarning", IdleWarning { reason, seconds: remaining.as_secs() });
+}
+
+/// Trạng thái của reaper giữa các vòng kiểm tra.
+pub struct Reaper {
+    policy: IdlePolicy,
+    /// Session đã được cảnh báo trong giai đoạn idle hiện tại.
+    warned: HashSet<String>,
+}
+
+impl Reaper {
+    #[must_use]
+    pub fn new(policy: IdlePolicy) -> Self {
+        Self { policy, warned: HashSet::new() }
+    }
+
+    /// Chạy một vòng kiểm tra: cảnh báo các session sắp hết hạn (mỗi giai đoạn idle chỉ cảnh báo một lần)
+    /// và kết thúc các session đã hết hạn. Trả về danh sách session đã bị dọn.
+    pub fn run_once(&mut self, registry: &SessionRegistry) -> Vec<(String, ReapReason)> {
+        let now = now_millis();
+        let mut reaped = Vec::new();
+
+        for session in registry.all_sessions() {
+            // Timeout riêng của session (từ template) thay cho `SESSION_IDLE_TIMEOUT_MINS`
+            let policy = session.idle_timeout.map_or(Cow::Borrowed(&self.policy), |timeout| {
+                Cow::Owned(IdlePolicy { idle_timeout: Some(timeout), ..self.policy.clone() })
+            });
+            match evaluate(&session.activity, session.client_count(), &policy, now) {
+                Decision::Keep => {
+                    self.warned.remove(&session.id);
+                }
+                Decision::Warn { reason, remaining } => {
+                    if self.warned.insert(session.id.clone()) {
+                        tracing::info!(session_id = %session.id, ?reason, secs = remaining.as_secs(), "warning clients before reaping");
+                        warn_clients(&session, reason, remaining);
+                    }
+                }
+                Decision::Reap(reason) => {
+                    self.warned.remove(&session.id);
+                    if registry.terminate_session(&session.id).is_some() {
+                        tracing::info!(session_id = %session.id, ?reason, "reaped inactive session");
+                        let _ = registry.global_tx().send(GlobalEvent::SessionReaped {
+                            id: session.id.clone(),
+                            reason,
+                        });
+                        reaped.push((session.id.clone(), reason));
+                    }
+                }
+            }
+        }
+
+        reaped
+    }
+}
+
+/// Khởi động tác vụ nền dọn session theo `policy`. Vẫn chạy khi chính sách toàn server tắt,
+/// vì session tạo từ template có thể có idle timeout riêng.
+pub fn spawn_reaper(registry: Arc<SessionRegistry>, policy: IdlePolicy) -> JoinHandle<()> {
+    if !policy.is_enabled() {
+        tracing::debug!("server-wide idle policy disabled, reaping only sessions with their own timeout");
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(policy.check_interval);
+        let mut reaper = Reaper::new(policy);
+        loop {
+            interval.tick().await;
+            reaper.run_once(&registry);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use tokio::sync::broadcast;
+
+    fn policy(idle: Option<u64>, detached: Option<u64>) -> IdlePolicy {
+        IdlePolicy {
+            idle_timeout: idle.map(Duration::from_secs),
+            detached_timeout: detached.map(Duration::from_secs),
+            warning: Duration::from_secs(30),
+            ..IdlePolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_evaluate_idle_warn_then_reap() {
+        let (tx, _) = broadcast::channel(10);
+        let session = Session::new("s".into(), Arc::new(crate::pty_manager::PtyManager::new()), tx);
+        let start = session.activity.last_activity();
+        let policy = policy(Some(120), None);
+
+        assert_eq!(evaluate(&session.activity, 1, &policy, start + 10_000), Decision::Keep);
+        assert_eq!(
+            evaluate(&session.activity, 1, &policy, start + 100_000),
+            Decision::Warn { reason: ReapReason::Idle, remaining: Duration::from_secs(20) }
+        );
+        // Không có client thì không cần cảnh báo
+        assert_eq!(evaluate(&session.activity, 0, &policy, start + 100_000), Decision::Keep);
+        assert_eq!(evaluate(&session.activity, 1, &policy, start + 120_000), Decision::Reap(ReapReason::Idle));
+
+        // Input mới reset đồng hồ idle
+        session.activity.last_input.store(start + 110_000, Ordering::Relaxed);
+        assert_eq!(evaluate(&session.activity, 1, &policy, start + 120_000), Decision::Keep);
+    }
+
+    #[test]
+    fn test_evaluate_detached() {
+        let (tx, _) = broadcast::channel(10);
+        let session = Session::new("s".into(), Arc::new(crate::pty_manager::PtyManager::new()), tx);
+        let start = session.activity.created_at;
+        let policy = policy(None, Some(60));
+
+        assert_eq!(evaluate(&session.activity, 0, &policy, start + 59_000), Decision::Keep);
+        assert_eq!(evaluate(&session.activity, 0, &policy, start + 60_000), Decision::Reap(ReapReason::Detached));
+        // Có client đang gắn vào thì không bị coi là detached
+        assert_eq!(evaluate(&session.activity, 1, &policy, start + 600_000), Decision::Keep);
+
+        // Đồng hồ detached tính từ lần detach cuối
+        session.activity.last_detach.store(start + 50_000, Ordering::Relaxed);
+        assert_eq!(evaluate(&session.activity, 0, &policy, start + 100_000), Decision::Keep);
+    }
+
+    #[tokio::test]
+    async fn test_reap_once_terminates_and_emits_event() {
+        let (gtx, mut grx) = broadcast::channel(10);
+        let registry = SessionRegistry::new(gtx);
+        // Không khởi động reader để output của shell không làm thay đổi mốc hoạt động
+        let (tx, _) = broadcast::channel(10);
+        let session = Session::new("idle-one".into(), Arc::new(crate::pty_manager::PtyManager::new()), tx);
+        registry.insert_session(session.clone());
+        let mut output = session.broadcast_tx.subscribe();
+        let _client = session.attach_client();
+
+        // Đẩy mốc hoạt động về quá khứ để session vào vùng cảnh báo
+        let past = now_millis() - 100_000;
+        session.activity.last_input.store(past, Ordering::Relaxed);
+        session.activity.last_output.store(past, Ordering::Relaxed);
+
+        let mut idle_reaper = Reaper::new(policy(Some(120), None));
+        assert!(idle_reaper.run_once(&registry).is_empty());
+        assert!(idle_reaper.warned.contains("idle-one"));
+        let msg = tokio::time::timeout(Duration::from_secs(1), output.recv()).await.unwrap().unwrap();
+        assert!(crate::session::is_control_message(&msg));
+        let warning: serde_json::Value = serde_json::from_slice(&msg).unwrap();
+        assert_eq!(warning["type"], "IdleWarning");
+        assert_eq!(warning["data"]["reason"], "idle");
+        assert_eq!(warning["data"]["seconds"], 20);
+
+        // Vòng sau không cảnh báo lại
+        assert!(idle_reaper.run_once(&registry).is_empty());
+        assert!(output.try_recv().is_err());
+
+        // Hết hạn
+        idle_reaper.policy.idle_timeout = Some(Duration::from_secs(90));
+        let reaped = idle_reaper.run_once(&registry);
+        assert_eq!(reaped, vec![("idle-one".to_string(), ReapReason::Idle)]);
+        assert!(registry.get_session("idle-one").is_none());
+
+        match grx.recv().await.unwrap() {
+            GlobalEvent::SessionReaped { id, reason } => {
+                assert_eq!(id, "idle-one");
+                assert_eq!(reason, ReapReason::Idle);
+            }
+            other => panic!("Expected SessionReaped, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_idle_timeout_overrides_policy() {
+        let (gtx, _) = broadcast::channel(10);
+        let registry = SessionRegistry::new(gtx);
+        let (tx, _) = broadcast::channel(10);
+        let mut session = Session::new("short".into(), Arc::new(crate::pty_manager::PtyManager::new()), tx.clone());
+        session.idle_timeout = Some(Duration::from_mins(1));
+        registry.insert_session(session);
+        registry.insert_session(Session::new("default".into(), Arc::new(crate::pty_manager::PtyManager::new()), tx));
+        let past = now_millis() - 90_000;
+        for s in registry.all_sessions() {
+            s.activity.last_input.store(past, Ordering::Relaxed);
+            s.activity.last_output.store(past, Ordering::Relaxed);
+        }
+
+        // Chính sách toàn server tắt: chỉ session có timeout riêng bị dọn
+        let mut reaper = Reaper::new(policy(None, None));
+        assert_eq!(reaper.run_once(&registry), vec![("short".to_string(), ReapReason::Idle)]);
+        assert!(registry.get_session("default").is_some());
+        registry.terminate_session("default");
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Ghi output của session thành file asciicast v2 (`<dir>/<id>-<created_at>.cast`),
+/// phát lại được bằng `asciinema play`.
+pub struct Recorder {
+    path: PathBuf,
+    file: BufWriter<File>,
+    started: Instant,
+    /// Phần cuối chưa đủ một ký tự UTF-8, ghép vào chunk kế tiếp.
+    pending: Vec<u8>,
+}
+
+impl Recorder {
+    /// Tạo file và ghi header với kích thước terminal ban đầu `(rows, cols)`.
+    ///
+    /// # Errors
+    /// Lỗi nếu không tạo được thư mục hoặc file.
+    pub fn create(dir: &Path, session_id: &str, created_at: u64, (rows, cols): (u16, u16)) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{session_id}-{created_at}.cast"));
+        let mut file = BufWriter::new(File::create_new(&path)?);
+        let header = serde_json::json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": created_at / 1000,
+            "env": { "TERM": "xterm-256color" },
+        });
+        writeln!(file, "{header}")?;
+        file.flush()?;
+        Ok(Self { path, file, started: Instant::now(), pending: Vec::new() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Ghi một event output (`[giây, "o", text]`). Chuỗi byte UTF-8 bị cắt giữa hai chunk được giữ lại.
+    ///
+    /// # Errors
+    /// Lỗi nếu không ghi được file.
+    pub fn output(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.pending.extend_from_slice(data);
+        let valid = match std::str::from_utf8(&self.pending) {
+            // `error_len() == None`: chuỗi chỉ bị cắt ở cuối, chờ chunk sau
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => self.pending.len(),
+        };
+        if valid == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.pending[..valid]).into_owned();
+        self.pending.drain(..valid);
+        self.event("o", &text)
+    }
+
+    /// Ghi event đổi kích thước (`[giây, "r", "COLSxROWS"]`).
+    ///
+    /// # Errors
+    /// Lỗi nếu không ghi được file.
+    pub fn resize(&mut self, rows: u16, cols: u16) -> std::io::Result<()> {
+        self.event("r", &format!("{cols}x{rows}"))
+    }
+
+    fn event(&mut self, kind: &str, data: &str) -> std::io::Result<()> {
+        let event = serde_json::json!([self.started.elapsed().as_secs_f64(), kind, data]);
+        writeln!(self.file, "{event}")?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorder_writes_asciicast() {
+        let dir = std::env::temp_dir().join(format!("bterm-rec-{}", uuid::Uuid::new_v4().simple()));
+        let mut recorder = Recorder::create(&dir, "rec", 1_700_000_000_000, (24, 80)).unwrap();
+        recorder.output(b"hello \xc3").unwrap();
+        recorder.output(b"\xa9\r\n").unwrap();
+        recorder.resize(30, 100).unwrap();
+
+        let content = std::fs::read_to_string(recorder.path()).unwrap();
+        let lines: Vec<serde_json::Value> = content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!((lines[0]["width"].as_u64(), lines[0]["height"].as_u64()), (Some(80), Some(24)));
+        assert_eq!(lines[0]["timestamp"], 1_700_000_000);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "hello ");
+        assert_eq!(lines[2][2], "é\r\n");
+        assert_eq!(lines[3][1], "r");
+        assert_eq!(lines[3][2], "100x30");
+        assert!(Recorder::create(&dir, "rec", 1_700_000_000_000, (24, 80)).is_err(), "never overwrites a recording");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
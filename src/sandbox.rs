@@ -0,0 +1,186 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::config::{CgroupLimits, ShellLimits};
+
+#[cfg(unix)]
+pub use nix::sys::resource::Resource;
+
+/// Danh sách (resource, giới hạn) được dựng sẵn trước khi fork, để `pre_exec`
+/// không phải cấp phát. Giới hạn không vượt quá hard limit hiện tại của server.
+#[cfg(unix)]
+pub fn rlimits(limits: &ShellLimits) -> Vec<(Resource, u64)> {
+    use nix::sys::resource::getrlimit;
+
+    [
+        (Resource::RLIMIT_CPU, limits.cpu_secs),
+        (Resource::RLIMIT_AS, limits.address_space_bytes),
+        (Resource::RLIMIT_NPROC, limits.max_processes),
+        (Resource::RLIMIT_NOFILE, limits.open_files),
+        (Resource::RLIMIT_CORE, limits.core_bytes),
+    ]
+    .into_iter()
+    .filter_map(|(resource, value)| {
+        let value = value?;
+        let hard = getrlimit(resource).map_or(value, |(_, hard)| hard);
+        Some((resource, value.min(hard)))
+    })
+    .collect()
+}
+
+/// Áp dụng rlimit cho tiến trình hiện tại (gọi trong `pre_exec`).
+#[cfg(unix)]
+pub fn apply_rlimits(limits: &[(Resource, u64)]) -> io::Result<()> {
+    for &(resource, value) in limits {
+        nix::sys::resource::setrlimit(resource, value, value)?;
+    }
+    Ok(())
+}
+
+/// Tài khoản Unix (từ passwd) mà shell sẽ chạy dưới quyền.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct UnixAccount {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+    /// Login shell trong passwd (rỗng = dùng shell mặc định của server).
+    pub shell: PathBuf,
+    /// Tên dạng C cho `initgroups`, dựng sẵn trước fork.
+    c_name: std::ffi::CString,
+}
+
+#[cfg(unix)]
+impl UnixAccount {
+    /// Tra cứu tài khoản trong passwd.
+    pub fn lookup(name: &str) -> io::Result<Self> {
+        let user = nix::unistd::User::from_name(name)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("unix user '{name}' does not exist")))?;
+        Ok(Self {
+            c_name: std::ffi::CString::new(user.name.as_str())?,
+            name: user.name,
+            uid: user.uid.as_raw(),
+            gid: user.gid.as_raw(),
+            home: user.dir,
+            shell: user.shell,
+        })
+    }
+
+    /// `true` nếu tiến trình hiện tại đã chạy dưới đúng uid/gid này.
+    pub fn is_current(&self) -> bool {
+        nix::unistd::geteuid().as_raw() == self.uid && nix::unistd::getegid().as_raw() == self.gid
+    }
+
+    /// Bỏ quyền: `initgroups`, `setgid`, rồi `setuid` (gọi trong `pre_exec`).
+    pub fn drop_privileges(&self) -> io::Result<()> {
+        unsafe {
+            if libc::initgroups(self.c_name.as_ptr(), self.gid) == -1
+                || libc::setgid(self.gid) == -1
+                || libc::setuid(self.uid) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// cgroup v2 riêng của một session. Khi drop, mọi tiến trình còn lại trong
+/// cgroup bị kill và thư mục được xóa.
+#[derive(Debug)]
+pub struct SessionCgroup {
+    path: PathBuf,
+}
+
+impl SessionCgroup {
+    /// Tạo `<parent>/<name>` và ghi `memory.max`/`pids.max`.
+    pub fn create(limits: &CgroupLimits, name: &str) -> io::Result<Self> {
+        // Bật controller cho cgroup con; có thể đã được bật sẵn hoặc không có quyền.
+        let _ = fs::write(limits.parent.join("cgroup.subtree_control"), "+memory +pids");
+
+        let path = limits.parent.join(name);
+        match fs::create_dir(&path) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        let cgroup = Self { path };
+        if let Some(max) = limits.memory_max {
+            cgroup.write("memory.max", &max.to_string())?;
+        }
+        if let Some(max) = limits.pids_max {
+            cgroup.write("pids.max", &max.to_string())?;
+        }
+        Ok(cgroup)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Mở `cgroup.procs` để tiến trình con tự ghi `0` (chính nó) vào trước khi exec.
+    pub fn open_procs(&self) -> io::Result<fs::File> {
+        fs::OpenOptions::new().write(true).open(self.path.join("cgroup.procs"))
+    }
+
+    /// Kill mọi tiến trình trong cgroup (`cgroup.kill`, Linux 5.14+).
+    pub fn kill(&self) {
+        let _ = self.write("cgroup.kill", "1");
+    }
+
+    fn write(&self, file: &str, value: &str) -> io::Result<()> {
+        fs::write(self.path.join(file), value)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", self.path.join(file).display())))
+    }
+}
+
+impl Drop for SessionCgroup {
+    fn drop(&mut self) {
+        self.kill();
+        // rmdir chỉ thành công khi cgroup không còn tiến trình nào
+        for _ in 0..20 {
+            if fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        tracing::warn!(path = %self.path.display(), "failed to remove session cgroup");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rlimits_only_configured_and_clamped() {
+        let limits = ShellLimits { open_files: Some(u64::MAX - 1), core_bytes: Some(0), ..ShellLimits::default() };
+        let list = rlimits(&limits);
+        assert_eq!(list.len(), 2);
+        let (_, hard) = nix::sys::resource::getrlimit(Resource::RLIMIT_NOFILE).unwrap();
+        assert!(list.contains(&(Resource::RLIMIT_NOFILE, (u64::MAX - 1).min(hard))));
+        assert!(list.contains(&(Resource::RLIMIT_CORE, 0)));
+        assert!(rlimits(&ShellLimits::default()).is_empty());
+    }
+
+    #[test]
+    fn test_cgroup_writes_limits() {
+        // Thư mục thường thay cho cgroupfs: chỉ kiểm tra các file được ghi
+        let parent = std::env::temp_dir().join(format!("bterm-cgroup-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir(&parent).unwrap();
+        let limits = CgroupLimits { parent: parent.clone(), memory_max: Some(1 << 30), pids_max: Some(256) };
+
+        let cgroup = SessionCgroup::create(&limits, "session-test").unwrap();
+        assert_eq!(cgroup.path(), parent.join("session-test"));
+        assert_eq!(fs::read_to_string(cgroup.path().join("memory.max")).unwrap(), "1073741824");
+        assert_eq!(fs::read_to_string(cgroup.path().join("pids.max")).unwrap(), "256");
+        assert!(cgroup.open_procs().is_err(), "cgroup.procs only exists on cgroupfs");
+        std::mem::forget(cgroup);
+
+        let missing = CgroupLimits { parent: parent.join("missing"), memory_max: None, pids_max: None };
+        assert!(SessionCgroup::create(&missing, "x").is_err());
+        fs::remove_dir_all(parent).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_SEARCH_CONTEXT: usize = 2;
+pub const MAX_SEARCH_CONTEXT: usize = 10;
+pub const DEFAULT_SEARCH_LIMIT: usize = 100;
+pub const MAX_SEARCH_LIMIT: usize = 1000;
+/// Giới hạn kích thước regex đã biên dịch, chặn pattern do client gửi quá lớn.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Một dòng khớp trong lịch sử output.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// Số thứ tự dòng (từ 0) trong lịch sử đã bỏ escape sequence.
+    pub line: usize,
+    pub text: String,
+    /// Vị trí khớp `[start, end)` tính theo ký tự trong `text`.
+    pub ranges: Vec<(usize, usize)>,
+    /// Tối đa `context` dòng trước và sau.
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub total_lines: usize,
+    pub matches: Vec<SearchMatch>,
+    /// `true` nếu còn dòng khớp ngoài `limit`.
+    pub truncated: bool,
+}
+
+/// Biên dịch truy vấn: chuỗi thường được escape, `regex = true` dùng nguyên pattern.
+///
+/// # Errors
+/// Lỗi nếu pattern không hợp lệ hoặc quá lớn.
+pub fn matcher(query: &str, regex: bool, ignore_case: bool) -> Result<Regex, regex::Error> {
+    let pattern = if regex { query.to_string() } else { regex::escape(query) };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+/// Tìm các dòng khớp `re`, kèm `context` dòng xung quanh, tối đa `limit` kết quả.
+#[must_use]
+pub fn search(lines: &[String], re: &Regex, context: usize, limit: usize) -> SearchResult {
+    let mut matches = Vec::new();
+    let mut truncated = false;
+    for (index, text) in lines.iter().enumerate() {
+        if !re.is_match(text) {
+            continue;
+        }
+        if matches.len() == limit {
+            truncated = true;
+            break;
+        }
+        let char_pos = |byte: usize| text[..byte].chars().count();
+        let ranges = re
+            .find_iter(text)
+            .filter(|m| !m.is_empty())
+            .map(|m| (char_pos(m.start()), char_pos(m.end())))
+            .collect();
+        let after_end = (index + 1 + context).min(lines.len());
+        matches.push(SearchMatch {
+            line: index,
+            text: text.clone(),
+            ranges,
+            before: lines[index.saturating_sub(context)..index].to_vec(),
+            after: lines[index + 1..after_end].to_vec(),
+        });
+    }
+    SearchResult { total_lines: lines.len(), matches, truncated }
+}
+
+/// Định dạng của `GET /api/sessions/{id}/scrollback`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollbackFormat {
+    /// Byte output nguyên bản, gồm cả escape sequence.
+    #[default]
+    Raw,
+    /// Văn bản đã bỏ escape sequence.
+    Text,
+    /// Trang HTML giữ màu và thuộc tính SGR.
+    Html,
+}
+
+impl ScrollbackFormat {
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Raw => "application/octet-stream",
+            Self::Text => "text/plain; charset=utf-8",
+            Self::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    /// Xuất `history` theo định dạng; `title` dùng làm tiêu đề trang HTML.
+    #[must_use]
+    pub fn render(self, history: Vec<u8>, title: &str) -> Vec<u8> {
+        match self {
+            Self::Raw => history,
+            Self::Text => {
+                let mut text = crate::ansi::text_lines(&history).join("\n");
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.into_bytes()
+            }
+            Self::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+                 <style>body{{margin:0;background:#000;color:#e5e5e5}}pre{{margin:0;padding:8px;font-family:monospace}}</style>\n\
+                 </head>\n<body><pre>{}</pre></body>\n</html>\n",
+                crate::ansi::escape_html(title),
+                crate::ansi::html_lines(&history),
+            )
+            .into_bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_search_plain_with_context() {
+        let lines = lines("$ make\ncc main.c\nerror: x undeclared\n1 error\n$ ");
+        let re = matcher("error", false, false).unwrap();
+        let result = search(&lines, &re, 1, 10);
+        assert_eq!(result.total_lines, 5);
+        assert!(!result.truncated);
+        assert_eq!(result.matches.len(), 2);
+        assert_eq!(
+            result.matches[0],
+            SearchMatch {
+                line: 2,
+                text: "error: x undeclared".to_string(),
+                ranges: vec![(0, 5)],
+                before: vec!["cc main.c".to_string()],
+                after: vec!["1 error".to_string()],
+            }
+        );
+        assert_eq!(result.matches[1].line, 3);
+        assert_eq!(result.matches[1].after, ["$ "]);
+
+        // Chuỗi thường không bị hiểu là regex
+        assert!(search(&lines, &matcher("x.c", false, false).unwrap(), 0, 10).matches.is_empty());
+        assert_eq!(search(&lines, &matcher("ERROR", false, true).unwrap(), 0, 10).matches.len(), 2);
+    }
+
+    #[test]
+    fn test_search_regex_ranges_and_limit() {
+        let lines = lines("héllo wörld wörd\nfoo\nwörk");
+        let re = matcher(r"w\w+d", true, false).unwrap();
+        let result = search(&lines, &re, 0, 10);
+        assert_eq!(result.matches[0].ranges, [(6, 11), (12, 16)]);
+        assert!(result.matches[0].before.is_empty());
+
+        let result = search(&lines, &matcher("w", false, false).unwrap(), 0, 1);
+        assert_eq!(result.matches.len(), 1);
+        assert!(result.truncated);
+
+        assert!(matcher("(unclosed", true, false).is_err());
+        assert!(matcher("(unclosed", false, false).is_ok());
+        assert!(matcher(r"\w{1000}{1000}", true, false).is_err(), "pattern too large");
+    }
+
+    #[test]
+    fn test_render_scrollback_formats() {
+        let history = b"\x1b]0;t\x07\x1b[32mok\x1b[0m <done>\r\n".to_vec();
+        assert_eq!(ScrollbackFormat::Raw.render(history.clone(), "s"), history);
+        assert_eq!(ScrollbackFormat::Text.render(history.clone(), "s"), b"ok <done>\n");
+        assert!(ScrollbackFormat::Text.render(Vec::new(), "s").is_empty());
+
+        let html = String::from_utf8(ScrollbackFormat::Html.render(history, "a<b")).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>a&lt;b</title>"));
+        assert!(html.contains("<pre><span style=\"color:#00cd00;\">ok</span> &lt;done&gt;\n</pre>"));
+        assert_eq!(ScrollbackFormat::Html.content_type(), "text/html; charset=utf-8");
+    }
+}
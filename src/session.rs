@@ -1,24 +1,584 @@
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::broadcast;
-use crate::pty_manager::PtyManager;
+use crate::backend::{BackendFactory, LocalPty, SessionBackend};
+use crate::config::SessionLimits;
+use crate::history::History;
+use crate::output::{Fanout, ReaderOptions};
 use crate::GlobalEvent;
+use crate::osc::{OscParser, TerminalState};
+use tracing::Instrument;
 
-#[derive(Clone, serde::Serialize)]
+#[derive(Clone, Debug, serde::Serialize)]
 pub struct SessionInfo {
     pub id: String,
+    /// Tên hiển thị (mặc định là `id`).
+    pub name: String,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    /// Window title do chương trình đặt qua OSC 0/2.
+    pub title: Option<String>,
+    /// Thời điểm tạo (unix ms).
+    pub created_at: u64,
+    pub owner: Option<i64>,
+    /// Loại backend (`pty`, ...).
+    pub backend: &'static str,
+    /// Lệnh chạy trong PTY (shell) hoặc đích kết nối của backend.
+    pub command: String,
+    /// Thư mục hiện tại theo OSC 7, nếu không có thì là thư mục lúc spawn.
+    pub cwd: Option<String>,
+    /// Kích thước PTY hiện tại.
+    pub size: Option<TermSize>,
+    /// Số WebSocket client đang gắn vào.
+    pub clients: usize,
+    pub pid: Option<u32>,
+    /// Lần cuối có input/output (unix ms).
+    pub last_activity: u64,
+    /// Exit code của shell nếu đã kết thúc.
+    pub exit_status: Option<u32>,
+    /// Tiến trình đang ở foreground của terminal.
+    pub foreground: Option<crate::foreground::ForegroundProcess>,
+    /// Output đang được ghi thành asciicast.
+    pub recording: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct TermSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+/// Lệnh và môi trường session được khởi động với, giữ lại để clone.
+#[derive(Debug, Clone, Default)]
+pub struct Launch {
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    /// Biến do user/template đặt (không gồm `BTERMINAL_*`, được sinh lại cho session mới).
+    pub env: Vec<(String, String)>,
+    pub recording: bool,
+}
+
+/// Thông tin mô tả session do người dùng đặt.
+#[derive(Debug, Clone, Default)]
+pub struct SessionMetadata {
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+}
+
+/// Thay đổi metadata từ `PATCH /api/sessions/{id}`; trường `None` được giữ nguyên.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct MetadataUpdate {
+    /// Chuỗi rỗng để quay về tên mặc định (`id`).
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Thay thế toàn bộ danh sách tag.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// Chuỗi rỗng để xóa mô tả.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+pub const MAX_NAME_LEN: usize = 128;
+pub const MAX_TAGS: usize = 16;
+pub const MAX_TAG_LEN: usize = 32;
+pub const MAX_DESCRIPTION_LEN: usize = 1024;
+
+impl MetadataUpdate {
+    /// Kiểm tra giới hạn độ dài trước khi áp dụng.
+    pub fn validate(&self) -> Result<(), SessionError> {
+        if self.name.as_ref().is_some_and(|n| n.chars().count() > MAX_NAME_LEN) {
+            return Err(SessionError::InvalidMetadata { reason: "name must be at most 128 characters" });
+        }
+        if let Some(tags) = &self.tags {
+            if tags.len() > MAX_TAGS {
+                return Err(SessionError::InvalidMetadata { reason: "at most 16 tags are allowed" });
+            }
+            if tags.iter().any(|t| t.trim().is_empty() || t.chars().count() > MAX_TAG_LEN) {
+                return Err(SessionError::InvalidMetadata { reason: "tags must be 1-32 characters" });
+            }
+        }
+        if self.description.as_ref().is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_LEN) {
+            return Err(SessionError::InvalidMetadata { reason: "description must be at most 1024 characters" });
+        }
+        Ok(())
+    }
+}
+
+/// Lỗi khi tạo session hoặc gắn client vào session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    /// Đã đạt giới hạn số session toàn server.
+    TooManySessions { limit: usize },
+    /// User đã sở hữu tối đa số session cho phép.
+    TooManySessionsForUser { limit: usize },
+    /// Session đã đạt số WebSocket client tối đa.
+    TooManyClients { limit: usize },
+    /// Không tìm thấy session.
+    NotFound { id: String },
+    /// Đã có session với ID này.
+    AlreadyExists { id: String },
+    /// ID không hợp lệ (xem `validate_id`).
+    InvalidId { reason: &'static str },
+    /// Tên/tag/mô tả vượt giới hạn (xem `MetadataUpdate::validate`).
+    InvalidMetadata { reason: &'static str },
+    /// User không phải owner của session và không phải admin.
+    Forbidden,
+    /// Không gửi được tín hiệu (ví dụ tiến trình đã kết thúc).
+    SignalFailed { reason: String },
+    /// Shell của session đã kết thúc, không còn tiến trình để đọc số liệu.
+    ProcessExited,
+    /// PID không thuộc cây tiến trình của session.
+    ProcessNotFound { pid: u32 },
+    /// Không spawn được shell (PTY, cgroup hoặc exec lỗi).
+    SpawnFailed { reason: String },
+    /// `SPAWN_AS_UNIX_USER` đang bật nhưng user chưa được map với tài khoản Unix.
+    UnixUserNotMapped,
+    /// Không có host SSH với tên này trong keystore.
+    UnknownSshHost { name: String },
+    /// Không kết nối hoặc xác thực được với host SSH.
+    ConnectFailed { reason: String },
+    /// User không có template với tên này.
+    UnknownTemplate { name: String },
+    /// Template không hợp lệ (xem `SessionTemplate::validate`).
+    InvalidTemplate { reason: &'static str },
+    /// Session yêu cầu recording nhưng server chưa cấu hình `SESSION_RECORDING_DIR`.
+    RecordingUnavailable,
+    /// Backend không hỗ trợ clone (chỉ PTY cục bộ).
+    CloneUnsupported { backend: &'static str },
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManySessions { limit } => write!(f, "session limit reached ({limit} sessions)"),
+            Self::TooManySessionsForUser { limit } => write!(f, "per-user session limit reached ({limit} sessions)"),
+            Self::TooManyClients { limit } => write!(f, "client limit reached for this session ({limit} clients)"),
+            Self::NotFound { id } => write!(f, "session '{id}' not found"),
+            Self::AlreadyExists { id } => write!(f, "session '{id}' already exists"),
+            Self::InvalidId { reason } => write!(f, "invalid session id: {reason}"),
+            Self::InvalidMetadata { reason } => write!(f, "invalid session metadata: {reason}"),
+            Self::Forbidden => write!(f, "only the session owner or an admin can do this"),
+            Self::SignalFailed { reason } => write!(f, "failed to deliver signal: {reason}"),
+            Self::ProcessExited => write!(f, "session process has exited"),
+            Self::ProcessNotFound { pid } => write!(f, "process {pid} does not belong to this session"),
+            Self::SpawnFailed { reason } => write!(f, "failed to start shell: {reason}"),
+            Self::UnixUserNotMapped => write!(f, "no unix account is mapped to this user; ask an admin to set one"),
+            Self::UnknownSshHost { name } => write!(f, "unknown ssh host '{name}'"),
+            Self::ConnectFailed { reason } => write!(f, "ssh connection failed: {reason}"),
+            Self::UnknownTemplate { name } => write!(f, "template '{name}' not found"),
+            Self::InvalidTemplate { reason } => write!(f, "invalid template: {reason}"),
+            Self::RecordingUnavailable => write!(f, "recording is not enabled on this server"),
+            Self::CloneUnsupported { backend } => write!(f, "{backend} sessions cannot be cloned"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Độ dài tối đa của session ID.
+pub const MAX_ID_LEN: usize = 64;
+
+/// ID hợp lệ gồm 1-64 ký tự `[A-Za-z0-9_-]` (an toàn khi đặt trong URL và tên file).
+pub fn validate_id(id: &str) -> Result<(), SessionError> {
+    if id.is_empty() {
+        return Err(SessionError::InvalidId { reason: "must not be empty" });
+    }
+    if id.len() > MAX_ID_LEN {
+        return Err(SessionError::InvalidId { reason: "must be at most 64 characters" });
+    }
+    if !id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+        return Err(SessionError::InvalidId { reason: "only letters, digits, '-' and '_' are allowed" });
+    }
+    Ok(())
+}
+
+/// Sinh ID mới cho session khi client không chỉ định.
+pub fn generate_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Tham số tạo session mới.
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    pub id: String,
+    /// User sở hữu session (`None` cho session tạo nội bộ/không qua API).
+    pub owner: Option<i64>,
+    /// Số byte lịch sử riêng cho session này, không vượt quá `SessionLimits::history_size`.
+    pub history_size: Option<usize>,
+    /// Số byte lịch sử tối đa ghi ra đĩa, không vượt quá `SessionLimits::spill_size`; `0` = tắt.
+    pub spill_size: Option<u64>,
+    /// Tên hiển thị.
+    pub name: Option<String>,
+    /// Tài khoản Unix chạy shell (chế độ `SPAWN_AS_UNIX_USER`).
+    pub unix_user: Option<String>,
+    /// Username bterminal của owner, được truyền vào shell qua `BTERMINAL_USER`.
+    pub username: Option<String>,
+    /// Backend của session; `None` = shell trên PTY cục bộ.
+    pub backend: Option<Arc<dyn BackendFactory>>,
+    /// Chương trình chạy thay cho shell, kèm tham số.
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    /// Thư mục làm việc; `None` = thư mục home.
+    pub cwd: Option<String>,
+    /// Biến môi trường thêm cho shell (không ghi đè được `BTERMINAL_*`).
+    pub env: Vec<(String, String)>,
+    /// Kích thước terminal ban đầu (rows, cols).
+    pub size: Option<(u16, u16)>,
+    /// Ghi output vào `SessionLimits::recording_dir`.
+    pub recording: bool,
+    /// Ghi đè `IdlePolicy::idle_timeout` cho session này.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl SessionOptions {
+    #[must_use]
+    pub const fn new(id: String) -> Self {
+        Self {
+            id,
+            owner: None,
+            history_size: None,
+            spill_size: None,
+            name: None,
+            unix_user: None,
+            username: None,
+            backend: None,
+            command: None,
+            args: Vec::new(),
+            cwd: None,
+            env: Vec::new(),
+            size: None,
+            recording: false,
+            idle_timeout: None,
+        }
+    }
+
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    #[must_use]
+    pub fn unix_user(mut self, unix_user: impl Into<String>) -> Self {
+        self.unix_user = Some(unix_user.into());
+        self
+    }
+
+    #[must_use]
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    #[must_use]
+    pub fn command(mut self, command: impl Into<String>, args: Vec<String>) -> Self {
+        self.command = Some(command.into());
+        self.args = args;
+        self
+    }
+
+    #[must_use]
+    pub fn cwd(mut self, cwd: impl Into<String>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    #[must_use]
+    pub fn envs(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.env.extend(vars);
+        self
+    }
+
+    #[must_use]
+    pub const fn size(mut self, rows: u16, cols: u16) -> Self {
+        self.size = Some((rows, cols));
+        self
+    }
+
+    #[must_use]
+    pub const fn recording(mut self, recording: bool) -> Self {
+        self.recording = recording;
+        self
+    }
+
+    #[must_use]
+    pub const fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Biến của user, sau đó là các biến giúp script trong session nhận biết session và user của nó.
+    fn env_vars(&self) -> Vec<(String, String)> {
+        let mut vars = self.env.clone();
+        vars.push(("BTERMINAL_SESSION_ID".to_string(), self.id.clone()));
+        if let Some(owner) = self.owner {
+            vars.push(("BTERMINAL_USER_ID".to_string(), owner.to_string()));
+        }
+        if let Some(username) = &self.username {
+            vars.push(("BTERMINAL_USER".to_string(), username.clone()));
+        }
+        vars
+    }
+
+    #[must_use]
+    pub const fn owner(mut self, user_id: i64) -> Self {
+        self.owner = Some(user_id);
+        self
+    }
+
+    #[must_use]
+    pub const fn history_size(mut self, bytes: usize) -> Self {
+        self.history_size = Some(bytes);
+        self
+    }
+
+    #[must_use]
+    pub const fn spill_size(mut self, bytes: u64) -> Self {
+        self.spill_size = Some(bytes);
+        self
+    }
+
+    #[must_use]
+    pub fn backend(mut self, factory: Arc<dyn BackendFactory>) -> Self {
+        self.backend = Some(factory);
+        self
+    }
+}
+
+impl From<String> for SessionOptions {
+    fn from(id: String) -> Self {
+        Self::new(id)
+    }
+}
+
+/// Thời điểm hiện tại dạng unix milliseconds.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// Tin nhắn điều khiển JSON (`SetSize`, `IdleWarning`, ...) đi chung broadcast channel với output PTY.
+pub fn is_control_message(data: &[u8]) -> bool {
+    data.starts_with(br#"{"type":"#)
+}
+
+/// Output của session do `monitor_session` phát cho các client sau khi đã lưu vào lịch sử.
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// `data` bắt đầu tại byte `offset` của output session (xem `History::end_offset`).
+    Data { offset: u64, data: axum::body::Bytes },
+    /// Tin nhắn điều khiển JSON (`SetSize`, `IdleWarning`, ...).
+    Control(String),
+    /// Backend đã kết thúc.
+    Exit,
+}
+
+/// Các mốc thời gian hoạt động của session (unix ms), dùng cho chính sách idle.
+#[derive(Debug)]
+pub struct SessionActivity {
+    pub created_at: u64,
+    pub last_input: AtomicU64,
+    pub last_output: AtomicU64,
+    pub last_attach: AtomicU64,
+    pub last_detach: AtomicU64,
+}
+
+impl SessionActivity {
+    const fn new(now: u64) -> Self {
+        Self {
+            created_at: now,
+            last_input: AtomicU64::new(now),
+            last_output: AtomicU64::new(now),
+            last_attach: AtomicU64::new(0),
+            last_detach: AtomicU64::new(0),
+        }
+    }
+
+    /// Lần cuối có input hoặc output.
+    pub fn last_activity(&self) -> u64 {
+        self.last_input.load(Ordering::Relaxed).max(self.last_output.load(Ordering::Relaxed))
+    }
+
+    /// Thời điểm session bắt đầu ở trạng thái không có client (lần detach cuối, hoặc lúc tạo).
+    pub fn detached_since(&self) -> u64 {
+        self.created_at
+            .max(self.last_attach.load(Ordering::Relaxed))
+            .max(self.last_detach.load(Ordering::Relaxed))
+    }
 }
 
 #[derive(Clone)]
 pub struct Session {
     #[allow(dead_code)]
     pub id: String,
-    pub pty_manager: Arc<PtyManager>,
+    pub owner: Option<i64>,
+    pub backend: Arc<dyn SessionBackend>,
     pub broadcast_tx: broadcast::Sender<Vec<u8>>,
-    pub history: Arc<Mutex<Vec<u8>>>,
+    /// Hàng đợi output đã gán offset của từng client, phát sau khi lưu vào `history`.
+    pub output: Arc<Fanout>,
+    pub history: Arc<Mutex<History>>,
     pub client_sizes: Arc<Mutex<std::collections::HashMap<uuid::Uuid, (u16, u16)>>>,
+    /// Số WebSocket client đang kết nối (kể cả client chưa gửi Resize).
+    pub clients: Arc<AtomicUsize>,
+    /// Số byte lịch sử output tối đa giữ lại trong bộ nhớ.
+    pub history_size: usize,
+    /// Số byte lịch sử tối đa ghi ra đĩa (`0` = không spill).
+    pub spill_size: u64,
+    pub metadata: Arc<Mutex<SessionMetadata>>,
+    pub activity: Arc<SessionActivity>,
+    pub terminal: Arc<Mutex<TerminalState>>,
+    /// Ghi đè thời gian idle tối đa của reaper cho session này.
+    pub idle_timeout: Option<Duration>,
+    /// Cách session được khởi động, dùng khi clone.
+    pub launch: Arc<Launch>,
+    /// File asciicast đang ghi, nếu bật recording.
+    pub recording: Option<std::path::PathBuf>,
 }
 
 impl Session {
+    /// Thông tin công khai của session trả về qua API.
+    pub fn info(&self) -> SessionInfo {
+        let metadata = self.metadata.lock().unwrap().clone();
+        let terminal = self.terminal.lock().unwrap().clone();
+        SessionInfo {
+            id: self.id.clone(),
+            name: metadata.name.unwrap_or_else(|| self.id.clone()),
+            tags: metadata.tags,
+            description: metadata.description,
+            title: terminal.title,
+            created_at: self.activity.created_at,
+            owner: self.owner,
+            backend: self.backend.kind(),
+            command: self.backend.command().to_string(),
+            cwd: terminal.cwd.or_else(|| self.backend.cwd().map(str::to_string)),
+            size: self.backend.size().map(|(rows, cols)| TermSize { rows, cols }),
+            clients: self.client_count(),
+            pid: self.backend.pid(),
+            last_activity: self.activity.last_activity(),
+            exit_status: self.backend.exit_status(),
+            foreground: self.backend.foreground(),
+            recording: self.recording.is_some(),
+        }
+    }
+
+    /// Thư mục hiện tại của terminal: theo OSC 7 nếu thư mục đó tồn tại, sau đó `/proc/<pid>/cwd`
+    /// của tiến trình foreground (hoặc shell), cuối cùng là thư mục lúc spawn.
+    pub fn current_dir(&self) -> Option<String> {
+        let reported = self.terminal.lock().unwrap().cwd.clone();
+        reported
+            .filter(|dir| std::path::Path::new(dir).is_dir())
+            .or_else(|| {
+                let pid = self.backend.foreground().map(|f| f.pid).or_else(|| self.backend.pid())?;
+                crate::procfs::cwd(pid)
+            })
+            .or_else(|| self.backend.cwd().map(str::to_string))
+    }
+
+    /// Options cho session mới cùng lệnh, môi trường, thư mục hiện tại và kích thước với session này.
+    #[must_use]
+    pub fn clone_options(&self, id: String) -> SessionOptions {
+        let mut options = SessionOptions::new(id)
+            .envs(self.launch.env.clone())
+            .recording(self.launch.recording)
+            .history_size(self.history_size)
+            .spill_size(self.spill_size);
+        if let Some(command) = &self.launch.command {
+            options = options.command(command.clone(), self.launch.args.clone());
+        }
+        if let Some(cwd) = self.current_dir() {
+            options = options.cwd(cwd);
+        }
+        if let Some((rows, cols)) = self.backend.size() {
+            options = options.size(rows, cols);
+        }
+        if let Some(timeout) = self.idle_timeout {
+            options = options.idle_timeout(timeout);
+        }
+        options
+    }
+
+    #[must_use]
+    pub fn new(id: String, backend: Arc<dyn SessionBackend>, broadcast_tx: broadcast::Sender<Vec<u8>>) -> Self {
+        Self {
+            id,
+            owner: None,
+            backend,
+            broadcast_tx,
+            output: Arc::new(Fanout::new(crate::config::DEFAULT_CLIENT_QUEUE_BYTES, false)),
+            history: Arc::new(Mutex::new(History::new(crate::config::DEFAULT_HISTORY_SIZE))),
+            client_sizes: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            clients: Arc::new(AtomicUsize::new(0)),
+            history_size: crate::config::DEFAULT_HISTORY_SIZE,
+            spill_size: 0,
+            metadata: Arc::new(Mutex::new(SessionMetadata::default())),
+            activity: Arc::new(SessionActivity::new(now_millis())),
+            terminal: Arc::new(Mutex::new(TerminalState::default())),
+            idle_timeout: None,
+            launch: Arc::default(),
+            recording: None,
+        }
+    }
+
+    /// Tăng số client; slot được trả lại khi `ClientGuard` bị drop (kể cả khi WS thoát sớm).
+    #[must_use]
+    pub fn attach_client(&self) -> ClientGuard {
+        self.clients.fetch_add(1, Ordering::SeqCst);
+        self.activity.last_attach.store(now_millis(), Ordering::Relaxed);
+        ClientGuard { clients: self.clients.clone(), activity: self.activity.clone() }
+    }
+
+    /// Giữ một slot client nếu session chưa đạt `limit`. Kiểm tra và tăng bộ đếm là một thao tác
+    /// nguyên tử nên các kết nối đồng thời không thể cùng vượt giới hạn.
+    pub fn try_attach_client(&self, limit: Option<usize>) -> Result<ClientGuard, SessionError> {
+        let Some(limit) = limit else {
+            return Ok(self.attach_client());
+        };
+        self.clients
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < limit).then_some(n + 1))
+            .map_err(|_| SessionError::TooManyClients { limit })?;
+        self.activity.last_attach.store(now_millis(), Ordering::Relaxed);
+        Ok(ClientGuard { clients: self.clients.clone(), activity: self.activity.clone() })
+    }
+
+    /// Áp dụng thay đổi metadata và trả về thông tin mới của session.
+    pub fn update_metadata(&self, update: MetadataUpdate) -> Result<SessionInfo, SessionError> {
+        update.validate()?;
+        {
+            let mut metadata = self.metadata.lock().unwrap();
+            if let Some(name) = update.name {
+                metadata.name = Some(name).filter(|n| !n.is_empty());
+            }
+            if let Some(tags) = update.tags {
+                let mut tags: Vec<String> = tags.into_iter().map(|t| t.trim().to_string()).collect();
+                tags.dedup();
+                metadata.tags = tags;
+            }
+            if let Some(description) = update.description {
+                metadata.description = Some(description).filter(|d| !d.is_empty());
+            }
+        }
+        Ok(self.info())
+    }
+
+    pub fn record_input(&self) {
+        self.activity.last_input.store(now_millis(), Ordering::Relaxed);
+    }
+
+    /// Gửi tin nhắn điều khiển `{"type": kind, "data": data}` tới tất cả client đang gắn vào session.
+    pub fn send_control<T: serde::Serialize>(&self, kind: &str, data: T) {
+        if let Ok(msg) = serde_json::to_vec(&ControlMessage { r#type: kind, data }) {
+            let _ = self.broadcast_tx.send(msg);
+        }
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.load(Ordering::SeqCst)
+    }
+
     pub fn update_client_size(&self, client_id: uuid::Uuid, rows: u16, cols: u16) {
         self.client_sizes.lock().unwrap().insert(client_id, (rows, cols));
         let sizes = self.client_sizes.lock().unwrap();
@@ -32,21 +592,19 @@ impl Session {
         }
     }
 
+    /// Kích thước PTY hiệu lực (MIN rows/cols của tất cả client), `None` nếu chưa có client nào.
+    pub fn current_size(&self) -> Option<(u16, u16)> {
+        let sizes = self.client_sizes.lock().unwrap();
+        min_size(&sizes)
+    }
+
     fn recalculate_pty_size(&self, sizes: &std::collections::HashMap<uuid::Uuid, (u16, u16)>) {
-        if sizes.is_empty() {
+        let Some((min_rows, min_cols)) = min_size(sizes) else {
             return;
-        }
-
-        let mut min_rows = u16::MAX;
-        let mut min_cols = u16::MAX;
-
-        for (r, c) in sizes.values() {
-            if *r < min_rows { min_rows = *r; }
-            if *c < min_cols { min_cols = *c; }
-        }
+        };
 
-        if min_rows > 0 && min_cols > 0 && min_rows != u16::MAX && min_cols != u16::MAX {
-            let _ = self.pty_manager.resize(min_rows, min_cols);
+        if min_rows > 0 && min_cols > 0 {
+            let _ = self.backend.resize(min_rows, min_cols);
             
             // Thông báo kích thước PTY mới cho tất cả các client để đồng bộ UI
             let msg = format!(r#"{{"type": "SetSize", "data": {{"rows": {min_rows}, "cols": {min_cols}}}}}"#);
@@ -55,86 +613,284 @@ impl Session {
     }
 }
 
+/// Guard giữ một slot client của session, giảm bộ đếm (và ghi nhận thời điểm detach) khi drop.
+pub struct ClientGuard {
+    clients: Arc<AtomicUsize>,
+    activity: Arc<SessionActivity>,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.clients.fetch_sub(1, Ordering::SeqCst);
+        self.activity.last_detach.store(now_millis(), Ordering::Relaxed);
+    }
+}
+
+/// Tin nhắn điều khiển gửi qua broadcast channel. `type` được serialize trước để khớp `is_control_message`.
+#[derive(serde::Serialize)]
+struct ControlMessage<'a, T> {
+    r#type: &'a str,
+    data: T,
+}
+
+fn min_size(sizes: &std::collections::HashMap<uuid::Uuid, (u16, u16)>) -> Option<(u16, u16)> {
+    let min_rows = sizes.values().map(|(r, _)| *r).min()?;
+    let min_cols = sizes.values().map(|(_, c)| *c).min()?;
+    Some((min_rows, min_cols))
+}
+
 pub struct SessionRegistry {
     sessions: Arc<Mutex<std::collections::HashMap<String, Session>>>,
+    /// Session đã được giữ chỗ nhưng đang spawn PTY (id -> owner), được tính vào giới hạn.
+    pending: Mutex<std::collections::HashMap<String, Option<i64>>>,
     global_tx: broadcast::Sender<GlobalEvent>,
+    limits: SessionLimits,
+}
+
+/// Chỗ đã giữ trong registry cho một session đang được tạo; tự giải phóng khi drop
+/// (kể cả khi spawn PTY thất bại hoặc panic).
+struct Reservation<'a> {
+    pending: &'a Mutex<std::collections::HashMap<String, Option<i64>>>,
+    id: String,
 }
 
-/// Hàm giám sát session: lưu trữ lịch sử output và tự động xóa session khỏi registry khi PTY kết thúc.
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&self.id);
+        }
+    }
+}
+
+/// Hàm giám sát session: lưu trữ lịch sử output, phát output kèm offset cho client qua `output`
+/// và tự động xóa session khỏi registry khi PTY kết thúc.
+#[allow(clippy::too_many_arguments)]
 async fn monitor_session(
     mut rx: broadcast::Receiver<Vec<u8>>,
-    history: Arc<Mutex<Vec<u8>>>,
+    history: Arc<Mutex<History>>,
+    output: Arc<Fanout>,
+    activity: Arc<SessionActivity>,
+    terminal: Arc<Mutex<TerminalState>>,
     registry_sessions: Arc<Mutex<std::collections::HashMap<String, Session>>>,
     session_id: String,
     global_tx: broadcast::Sender<GlobalEvent>,
+    mut recorder: Option<crate::recording::Recorder>,
 ) {
+    let mut osc = OscParser::new();
     loop {
         match rx.recv().await {
             Ok(data) => {
                 if data.is_empty() {
                     // PTY kết thúc, xóa session khỏi registry
+                    tracing::info!("PTY exited, removing session");
                     registry_sessions.lock().unwrap().remove(&session_id);
                     // Thông báo cho toàn bộ các Dashboard khác
                     let _ = global_tx.send(GlobalEvent::SessionDeleted(session_id));
                     break;
                 }
-                // Lưu lịch sử (giới hạn 100KB)
-                let mut buffer = history.lock().unwrap();
-                buffer.extend_from_slice(&data);
-                let len = buffer.len();
-                if len > 102_400 {
-                    buffer.drain(..len - 102_400);
+                // Tin nhắn điều khiển không phải output của PTY: không lưu vào lịch sử
+                if is_control_message(&data) {
+                    if let Some(recorder) = recorder.as_mut() {
+                        record_resize(recorder, &data);
+                    }
+                    output.publish(&Output::Control(String::from_utf8_lossy(&data).into_owned()));
+                    continue;
+                }
+                activity.last_output.store(now_millis(), Ordering::Relaxed);
+                if let Some(rec) = recorder.as_mut()
+                    && let Err(e) = rec.output(&data)
+                {
+                    tracing::error!(error = %e, path = %rec.path().display(), "recording failed, stopping it");
+                    recorder = None;
                 }
+                // Theo dõi title/cwd (OSC 0/2/7), output vẫn giữ nguyên
+                let events = osc.feed(&data);
+                if !events.is_empty() {
+                    let mut state = terminal.lock().unwrap();
+                    let mut changed = false;
+                    for event in events {
+                        changed |= state.apply(event);
+                    }
+                    let snapshot = changed.then(|| state.clone());
+                    drop(state);
+                    if let Some(TerminalState { title, cwd }) = snapshot {
+                        tracing::debug!(?title, ?cwd, "terminal state changed");
+                        let _ = global_tx.send(GlobalEvent::TerminalStateChanged { id: session_id.clone(), title, cwd });
+                    }
+                }
+                // Lưu lịch sử (ring buffer `Session::history_size` byte, phần cũ hơn ghi ra đĩa nếu bật spill).
+                // Phát output khi còn giữ khóa để client đăng ký kèm snapshot không mất hay lặp byte nào.
+                let mut history = history.lock().unwrap();
+                let offset = history.end_offset();
+                history.push(&data);
+                output.publish(&Output::Data { offset, data: data.into() });
+                drop(history);
             }
-            Err(broadcast::error::RecvError::Lagged(_)) => {
+            Err(broadcast::error::RecvError::Lagged(n)) => {
                 // Tiếp tục nếu bị lag (mất một số message)
+                tracing::warn!(skipped = n, "session monitor lagged, history may be incomplete");
+                osc.reset();
+                crate::metrics::record_lag("monitor", n);
             }
             #[cfg(not(tarpaulin_include))]
             Err(_) => break, // Channel bị đóng
         }
     }
+    output.publish(&Output::Exit);
+    output.close();
+}
+
+/// Ghi event resize vào bản ghi khi kích thước PTY thay đổi (tin nhắn `SetSize`).
+fn record_resize(recorder: &mut crate::recording::Recorder, msg: &[u8]) {
+    let Ok(msg) = serde_json::from_slice::<serde_json::Value>(msg) else {
+        return;
+    };
+    if msg["type"] != "SetSize" {
+        return;
+    }
+    let size = |key: &str| msg["data"][key].as_u64().and_then(|v| u16::try_from(v).ok());
+    if let (Some(rows), Some(cols)) = (size("rows"), size("cols")) {
+        let _ = recorder.resize(rows, cols);
+    }
 }
 
 impl SessionRegistry {
     #[must_use]
     pub fn new(global_tx: broadcast::Sender<GlobalEvent>) -> Self {
+        Self::with_limits(global_tx, SessionLimits::default())
+    }
+
+    #[must_use]
+    pub fn with_limits(global_tx: broadcast::Sender<GlobalEvent>, limits: SessionLimits) -> Self {
         Self {
             sessions: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            pending: Mutex::new(std::collections::HashMap::new()),
             global_tx,
+            limits,
         }
     }
 
-    #[must_use]
-    pub fn create_session(&self, id: String) -> Session {
-        let pty_manager = Arc::new(PtyManager::new());
+    pub const fn limits(&self) -> &SessionLimits {
+        &self.limits
+    }
+
+    /// `true` nếu registry đã đạt giới hạn session toàn server.
+    pub fn is_at_capacity(&self) -> bool {
+        self.limits.max_sessions.is_some_and(|max| self.len() >= max)
+    }
+
+    /// Kiểm tra giới hạn và giữ chỗ cho session dưới lock; việc spawn PTY diễn ra sau khi nhả lock.
+    fn reserve(&self, options: &SessionOptions) -> Result<Reservation<'_>, SessionError> {
+        let sessions = self.sessions.lock().unwrap();
+        let mut pending = self.pending.lock().unwrap();
+        if sessions.contains_key(&options.id) || pending.contains_key(&options.id) {
+            return Err(SessionError::AlreadyExists { id: options.id.clone() });
+        }
+        if let Some(limit) = self.limits.max_sessions
+            && sessions.len() + pending.len() >= limit
+        {
+            return Err(SessionError::TooManySessions { limit });
+        }
+        if let (Some(limit), Some(owner)) = (self.limits.max_sessions_per_user, options.owner) {
+            let owned = sessions.values().filter(|s| s.owner == Some(owner)).count()
+                + pending.values().filter(|o| **o == Some(owner)).count();
+            if owned >= limit {
+                return Err(SessionError::TooManySessionsForUser { limit });
+            }
+        }
+        pending.insert(options.id.clone(), options.owner);
+        drop(pending);
+        drop(sessions);
+        Ok(Reservation { pending: &self.pending, id: options.id.clone() })
+    }
+
+    pub fn create_session(&self, options: impl Into<SessionOptions>) -> Result<Session, SessionError> {
+        let options = options.into();
+        let span = tracing::info_span!("session", session_id = %options.id);
+        let _enter = span.enter();
+
+        validate_id(&options.id)?;
+        if options.recording && self.limits.recording_dir.is_none() {
+            return Err(SessionError::RecordingUnavailable);
+        }
+        let reservation = self.reserve(&options)?;
+        let spawn_options = crate::pty_manager::SpawnOptions {
+            session_id: Some(options.id.clone()),
+            limits: self.limits.shell.clone(),
+            run_as: options.unix_user.clone(),
+            environment: self.limits.environment.clone(),
+            env: options.env_vars(),
+            command: options.command.clone(),
+            args: options.args.clone(),
+            cwd: options.cwd.clone(),
+            size: options.size,
+        };
+        let id = options.id;
+        let factory = options.backend.unwrap_or_else(|| Arc::new(LocalPty));
+        let backend = factory.spawn(&spawn_options).map_err(|e| {
+            tracing::error!(error = %e, backend = ?factory, "failed to spawn session backend");
+            SessionError::SpawnFailed { reason: e.to_string() }
+        })?;
         let (tx, _) = broadcast::channel(100);
-        let history = Arc::new(Mutex::new(Vec::new()));
-        let client_sizes = Arc::new(Mutex::new(std::collections::HashMap::new()));
-
-        let session = Session {
-            id: id.clone(),
-            pty_manager: pty_manager.clone(),
-            broadcast_tx: tx.clone(),
-            history: history.clone(),
-            client_sizes,
+        let mut session = Session::new(id.clone(), backend.clone(), tx.clone());
+        session.owner = options.owner;
+        session.metadata.lock().unwrap().name = options.name;
+        session.history_size = options
+            .history_size
+            .map_or(self.limits.history_size, |size| size.min(self.limits.history_size));
+        let mut history = History::new(session.history_size);
+        if let Some(dir) = &self.limits.spill_dir {
+            let size = options.spill_size.map_or(self.limits.spill_size, |size| size.min(self.limits.spill_size));
+            match history.spill_to(dir, size) {
+                Ok(()) => session.spill_size = size,
+                Err(e) => tracing::warn!(error = %e, dir = %dir.display(), "cannot create history spill file, keeping history in memory only"),
+            }
+        }
+        session.history = Arc::new(Mutex::new(history));
+        session.idle_timeout = options.idle_timeout;
+        session.launch = Arc::new(Launch {
+            command: options.command,
+            args: options.args,
+            env: options.env,
+            recording: options.recording,
+        });
+        let recorder = match &self.limits.recording_dir {
+            Some(dir) if options.recording => {
+                let size = backend.size().unwrap_or((24, 80));
+                let recorder = crate::recording::Recorder::create(dir, &id, session.activity.created_at, size).map_err(|e| {
+                    tracing::error!(error = %e, "failed to create session recording");
+                    backend.shutdown();
+                    SessionError::SpawnFailed { reason: format!("cannot create recording: {e}") }
+                })?;
+                session.recording = Some(recorder.path().to_path_buf());
+                Some(recorder)
+            }
+            _ => None,
         };
 
         let rx = tx.subscribe();
 
-        // Khởi động PTY reader thread
-        pty_manager.start_reader(tx);
+        // Khởi động reader của backend (thread đọc PTY với backend cục bộ)
+        session.output = Arc::new(Fanout::new(self.limits.output.client_queue_bytes, self.limits.output.pause_when_behind));
+        backend.start_reader(tx, ReaderOptions { coalesce: self.limits.output.coalesce, flow: session.output.flow().clone() });
 
         // Khởi động luồng giám sát session (lưu lịch sử và tự dọn dẹp)
         tokio::spawn(monitor_session(
             rx,
-            history,
+            session.history.clone(),
+            session.output.clone(),
+            session.activity.clone(),
+            session.terminal.clone(),
             Arc::clone(&self.sessions),
             id.clone(),
             self.global_tx.clone(),
-        ));
+            recorder,
+        ).in_current_span());
 
+        tracing::info!(owner = ?session.owner, "session spawned");
         self.sessions.lock().unwrap().insert(id, session.clone());
-        session
+        drop(reservation);
+        Ok(session)
     }
 
     #[must_use]
@@ -147,20 +903,57 @@ impl SessionRegistry {
         self.sessions
             .lock()
             .unwrap()
-            .keys()
-            .map(|id| SessionInfo { id: id.clone() })
+            .values()
+            .map(Session::info)
             .collect()
     }
 
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Snapshot của tất cả session hiện có (dùng cho metrics và các tác vụ nền).
+    #[must_use]
+    pub fn all_sessions(&self) -> Vec<Session> {
+        self.sessions.lock().unwrap().values().cloned().collect()
+    }
+
     pub fn remove_session(&self, id: &str) {
         self.sessions.lock().unwrap().remove(id);
     }
+
+    /// Gỡ session khỏi registry và kill PTY ngay cả khi còn client đang gắn vào.
+    /// Trả về session đã bị gỡ (nếu có).
+    pub fn terminate_session(&self, id: &str) -> Option<Session> {
+        let session = self.sessions.lock().unwrap().remove(id)?;
+        // Reader đang tạm dừng phải đọc được tới EOF để client nhận `Exit`
+        session.output.close();
+        session.backend.shutdown();
+        Some(session)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn insert_session(&self, session: Session) {
+        self.sessions.lock().unwrap().insert(session.id.clone(), session);
+    }
+
+    pub const fn global_tx(&self) -> &broadcast::Sender<GlobalEvent> {
+        &self.global_tx
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::time::Duration;
+    use crate::pty_manager::PtyManager;
+    use crate::output::Received;
 
     fn setup_registry() -> (SessionRegistry, broadcast::Receiver<GlobalEvent>) {
         let (tx, rx) = broadcast::channel(10);
@@ -171,29 +964,29 @@ mod tests {
     async fn test_monitor_session_history_and_cleanup() {
         let (tx, rx) = broadcast::channel(10);
         let (gtx, mut grx) = broadcast::channel(10);
-        let history = Arc::new(Mutex::new(Vec::new()));
         let sessions = Arc::new(Mutex::new(std::collections::HashMap::new()));
         let session_id = "test-session".to_string();
 
         // Giả lập session trong registry
         let pty_manager = Arc::new(PtyManager::new());
-        let client_sizes = Arc::new(Mutex::new(std::collections::HashMap::new()));
-        let session = Session {
-            id: session_id.clone(),
-            pty_manager,
-            broadcast_tx: tx.clone(),
-            history: history.clone(),
-            client_sizes,
-        };
+        let session = Session::new(session_id.clone(), pty_manager, tx.clone());
+        let history = session.history.clone();
+        let activity = session.activity.clone();
+        let subscription = session.output.subscribe();
+        let output = session.output.clone();
         sessions.lock().unwrap().insert(session_id.clone(), session);
 
         // Chạy monitor_session
         tokio::spawn(monitor_session(
             rx,
             history.clone(),
+            output,
+            activity,
+            Arc::default(),
             sessions.clone(),
             session_id.clone(),
             gtx,
+            None,
         ));
 
         // Gửi dữ liệu
@@ -203,9 +996,10 @@ mod tests {
         // Kiểm tra history
         {
             let h = history.lock().unwrap();
-            assert_eq!(h.as_slice(), b"hello");
+            assert_eq!(h.snapshot(), b"hello");
             drop(h);
         }
+        tx.send(b" world".to_vec()).unwrap();
 
         // Gửi tín hiệu kết thúc
         tx.send(Vec::new()).unwrap();
@@ -224,6 +1018,203 @@ mod tests {
             GlobalEvent::SessionDeleted(id) => assert_eq!(id, session_id),
             _ => panic!("Expected SessionDeleted event"),
         }
+
+        // Output được phát cho client kèm offset, sau đó là Exit
+        let mut received = Vec::new();
+        while received.last().is_none_or(|last| last != "exit") {
+            received.push(match subscription.recv().await {
+                Received::Output(Output::Data { offset, data }) => format!("{offset}:{}", String::from_utf8_lossy(&data)),
+                Received::Output(Output::Control(text)) => text,
+                Received::Output(Output::Exit) => "exit".to_string(),
+                Received::Overflowed(_) => "overflow".to_string(),
+            });
+        }
+        assert_eq!(received, ["0:hello", "5: world", "exit"]);
+    }
+
+    #[tokio::test]
+    async fn test_monitor_session_truncates_history() {
+        let (tx, rx) = broadcast::channel(10);
+        let (gtx, _grx) = broadcast::channel(10);
+        let sessions = Arc::new(Mutex::new(std::collections::HashMap::new()));
+        let history = Arc::new(Mutex::new(History::new(8)));
+
+        let activity = Arc::new(SessionActivity::new(0));
+        tokio::spawn(monitor_session(rx, history.clone(), Arc::new(Fanout::new(8, false)), activity, Arc::default(), sessions, "trunc".to_string(), gtx, None));
+
+        tx.send(b"0123456789".to_vec()).unwrap();
+        tx.send(b"abcdef".to_vec()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Chỉ giữ lại 8 byte cuối cùng
+        assert_eq!(history.lock().unwrap().snapshot(), b"89abcdef");
+    }
+
+    #[tokio::test]
+    async fn test_control_messages_skip_history_and_activity() {
+        let (tx, rx) = broadcast::channel(10);
+        let (gtx, _grx) = broadcast::channel(10);
+        let sessions = Arc::new(Mutex::new(std::collections::HashMap::new()));
+        let session = Session::new("ctrl".to_string(), Arc::new(PtyManager::new()), tx);
+        session.activity.last_output.store(1, Ordering::Relaxed);
+
+        tokio::spawn(monitor_session(
+            rx,
+            session.history.clone(),
+            session.output.clone(),
+            session.activity.clone(),
+            session.terminal.clone(),
+            sessions,
+            "ctrl".to_string(),
+            gtx,
+            None,
+        ));
+
+        session.send_control("IdleWarning", serde_json::json!({ "reason": "idle", "seconds": 30 }));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(session.history.lock().unwrap().is_empty());
+        assert_eq!(session.activity.last_output.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_monitor_tracks_osc_title_and_cwd() {
+        let (tx, rx) = broadcast::channel(10);
+        let (gtx, mut grx) = broadcast::channel(10);
+        let sessions = Arc::new(Mutex::new(std::collections::HashMap::new()));
+        let session = Session::new("osc".to_string(), Arc::new(PtyManager::new()), tx.clone());
+
+        tokio::spawn(monitor_session(
+            rx,
+            session.history.clone(),
+            session.output.clone(),
+            session.activity.clone(),
+            session.terminal.clone(),
+            sessions,
+            "osc".to_string(),
+            gtx,
+            None,
+        ));
+
+        let output = b"\x1b]7;file://host/home/me/repo\x07\x1b]2;vim main.rs\x07";
+        tx.send(output.to_vec()).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(1), grx.recv()).await.unwrap().unwrap();
+        match event {
+            GlobalEvent::TerminalStateChanged { id, title, cwd } => {
+                assert_eq!(id, "osc");
+                assert_eq!(title.as_deref(), Some("vim main.rs"));
+                assert_eq!(cwd.as_deref(), Some("/home/me/repo"));
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        // Output giữ nguyên trong lịch sử
+        assert_eq!(session.history.lock().unwrap().snapshot(), output);
+        let info = session.info();
+        assert_eq!(info.title.as_deref(), Some("vim main.rs"));
+        assert_eq!(info.cwd.as_deref(), Some("/home/me/repo"));
+
+        // Giá trị không đổi thì không phát event
+        tx.send(b"\x1b]2;vim main.rs\x07".to_vec()).unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(100), grx.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_current_dir_and_clone_options() {
+        let (tx, _) = broadcast::channel(10);
+        let registry = SessionRegistry::new(tx);
+        let options = SessionOptions::new("src".to_string())
+            .command("/bin/sh", Vec::new())
+            .envs([("PROJECT".to_string(), "demo".to_string())])
+            .size(30, 100)
+            .idle_timeout(Duration::from_mins(5));
+        let session = registry.create_session(options).unwrap();
+        let wait_for_dir = |expected: &'static str| {
+            let session = session.clone();
+            async move {
+                for _ in 0..100 {
+                    if session.current_dir().as_deref() == Some(expected) {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                panic!("cwd is {:?}, expected {expected}", session.current_dir());
+            }
+        };
+
+        // Shell không báo OSC 7: đọc `/proc/<pid>/cwd`
+        session.backend.write(b"cd /usr\n").unwrap();
+        wait_for_dir("/usr").await;
+        // OSC 7 được ưu tiên nếu thư mục tồn tại
+        session.backend.write(b"printf '\\033]7;file://host/tmp\\007'\n").unwrap();
+        wait_for_dir("/tmp").await;
+        session.backend.write(b"printf '\\033]7;file://host/no/such/dir\\007'\n").unwrap();
+        wait_for_dir("/usr").await;
+
+        let clone = session.clone_options("copy".to_string());
+        assert_eq!(clone.command.as_deref(), Some("/bin/sh"));
+        assert_eq!(clone.env, [("PROJECT".to_string(), "demo".to_string())]);
+        assert_eq!(clone.cwd.as_deref(), Some("/usr"));
+        assert_eq!(clone.size, Some((30, 100)));
+        assert_eq!(clone.idle_timeout, Some(Duration::from_mins(5)));
+        assert_eq!(clone.history_size, Some(session.history_size));
+        registry.terminate_session("src");
+    }
+
+    #[tokio::test]
+    async fn test_create_session_history_size_option() {
+        let (tx, _) = broadcast::channel(10);
+        let limits = SessionLimits { history_size: 4096, ..SessionLimits::default() };
+        let registry = SessionRegistry::with_limits(tx, limits);
+
+        let default = registry.create_session("hist-default".to_string()).unwrap();
+        assert_eq!(default.history_size, 4096);
+
+        let small = registry.create_session(SessionOptions::new("hist-small".to_string()).history_size(1024)).unwrap();
+        assert_eq!(small.history_size, 1024);
+
+        // Không vượt quá giới hạn cấu hình của server
+        let large = registry.create_session(SessionOptions::new("hist-large".to_string()).history_size(1 << 20)).unwrap();
+        assert_eq!(large.history_size, 4096);
+    }
+
+    #[tokio::test]
+    async fn test_create_session_spills_history_to_disk() {
+        let (tx, _) = broadcast::channel(10);
+        let dir = std::env::temp_dir().join(format!("bterm-spill-{}", uuid::Uuid::new_v4().simple()));
+        let limits = SessionLimits { history_size: 16, spill_dir: Some(dir.clone()), spill_size: 1024, ..SessionLimits::default() };
+        let registry = SessionRegistry::with_limits(tx, limits);
+        let quiet = |id: &str| SessionOptions::new(id.to_string()).command("sleep", vec!["30".to_string()]);
+
+        let session = registry.create_session(quiet("spill")).unwrap();
+        assert_eq!(session.spill_size, 1024);
+        let output: Vec<u8> = (0..200u8).collect();
+        session.history.lock().unwrap().push(&output);
+        let history = session.history.lock().unwrap();
+        assert_eq!(history.snapshot(), &output[184..]);
+        assert_eq!(history.spilled(), 184);
+        assert_eq!(history.full(), output);
+        drop(history);
+
+        assert_eq!(registry.create_session(quiet("spill-off").spill_size(0)).unwrap().spill_size, 0);
+        assert_eq!(registry.create_session(quiet("spill-big").spill_size(1 << 30)).unwrap().spill_size, 1024);
+        for id in ["spill", "spill-off", "spill-big"] {
+            registry.terminate_session(id);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_try_attach_client_respects_limit() {
+        let (tx, _) = broadcast::channel(10);
+        let session = Session::new("test-limit".to_string(), Arc::new(PtyManager::new()), tx);
+
+        let first = session.try_attach_client(Some(1)).unwrap();
+        assert_eq!(session.try_attach_client(Some(1)).err(), Some(SessionError::TooManyClients { limit: 1 }));
+        assert_eq!(session.client_count(), 1);
+
+        drop(first);
+        assert!(session.try_attach_client(Some(1)).is_ok());
     }
 
     #[tokio::test]
@@ -232,7 +1223,7 @@ mod tests {
         let session_id = "test-reg".to_string();
         
         // Create
-        let _ = registry.create_session(session_id.clone());
+        registry.create_session(session_id.clone()).unwrap();
         
         // Get
         let session = registry.get_session(&session_id);
@@ -248,20 +1239,26 @@ mod tests {
         assert!(registry.get_session(&session_id).is_none());
     }
 
+    #[tokio::test]
+    async fn test_client_guard_releases_slot() {
+        let (tx, _) = broadcast::channel(10);
+        let session = Session::new("test-guard".to_string(), Arc::new(PtyManager::new()), tx);
+
+        let first = session.attach_client();
+        let second = session.attach_client();
+        assert_eq!(session.client_count(), 2);
+
+        drop(first);
+        assert_eq!(session.client_count(), 1);
+        drop(second);
+        assert_eq!(session.client_count(), 0);
+    }
+
     #[tokio::test]
     async fn test_recalculate_pty_size_min_logic() {
         let pty_manager = Arc::new(PtyManager::new());
         let (tx, _) = broadcast::channel(10);
-        let history = Arc::new(Mutex::new(Vec::new()));
-        let client_sizes = Arc::new(Mutex::new(std::collections::HashMap::new()));
-
-        let session = Session {
-            id: "test-resize".to_string(),
-            pty_manager: pty_manager.clone(),
-            broadcast_tx: tx,
-            history,
-            client_sizes,
-        };
+        let session = Session::new("test-resize".to_string(), pty_manager, tx);
 
         // Giả lập 3 client với kích thước khác nhau
         let client1 = uuid::Uuid::new_v4();
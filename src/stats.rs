@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use crate::pty_manager::ResourceUsage;
+use crate::session::SessionRegistry;
+use crate::GlobalEvent;
+
+/// Số liệu tài nguyên của một session trong event `SessionStats`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionUsage {
+    pub id: String,
+    #[serde(flatten)]
+    pub usage: ResourceUsage,
+}
+
+/// Lấy mẫu mọi session; session đã kết thúc tiến trình bị bỏ qua.
+pub fn collect(registry: &SessionRegistry) -> Vec<SessionUsage> {
+    registry
+        .all_sessions()
+        .into_iter()
+        .filter_map(|session| {
+            let usage = session.backend.sample_usage()?;
+            Some(SessionUsage { id: session.id, usage })
+        })
+        .collect()
+}
+
+/// Định kỳ phát `GlobalEvent::SessionStats`. Không làm gì nếu `period` là `None`.
+pub fn spawn_stats_sampler(registry: Arc<SessionRegistry>, period: Option<Duration>) -> Option<JoinHandle<()>> {
+    let period = period?;
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let usage = tokio::task::spawn_blocking({
+                let registry = registry.clone();
+                move || collect(&registry)
+            })
+            .await
+            .unwrap_or_default();
+            if !usage.is_empty() {
+                let _ = registry.global_tx().send(GlobalEvent::SessionStats(usage));
+            }
+        }
+    }))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    #[tokio::test]
+    async fn test_sampler_broadcasts_stats() {
+        let (tx, mut rx) = broadcast::channel(10);
+        let registry = Arc::new(SessionRegistry::new(tx));
+        registry.create_session("s1".to_string()).unwrap();
+        registry.create_session("s2".to_string()).unwrap();
+
+        let handle = spawn_stats_sampler(registry.clone(), Some(Duration::from_millis(50))).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await.unwrap().unwrap();
+        handle.abort();
+
+        let GlobalEvent::SessionStats(mut usage) = event else {
+            panic!("unexpected event: {event:?}");
+        };
+        usage.sort_by(|a, b| a.id.cmp(&b.id));
+        assert_eq!(usage.iter().map(|u| u.id.as_str()).collect::<Vec<_>>(), ["s1", "s2"]);
+        assert!(usage.iter().all(|u| u.usage.processes >= 1));
+
+        let json = serde_json::to_value(&usage[0]).unwrap();
+        assert!(json["cpu_percent"].is_number() && json["rss_bytes"].is_number());
+
+        assert!(spawn_stats_sampler(registry.clone(), None).is_none());
+        registry.terminate_session("s1");
+        registry.terminate_session("s2");
+    }
+}
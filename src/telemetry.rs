@@ -0,0 +1,97 @@
+use axum::http::{HeaderName, Request};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tracing::Span;
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// Header mang request id, được sinh cho mỗi request HTTP/WS nếu client không gửi sẵn.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+type MakeSpanFn<B> = fn(&Request<B>) -> Span;
+
+/// Filter mặc định khi không có `RUST_LOG`.
+const DEFAULT_FILTER: &str = "bterminal=info,tower_http=info";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "text" | "pretty" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow::anyhow!("unknown log format: {other}")),
+        }
+    }
+}
+
+/// Khởi tạo subscriber toàn cục.
+///
+/// - `RUST_LOG`: filter theo cú pháp `EnvFilter` (mặc định `bterminal=info,tower_http=info`).
+/// - `LOG_FORMAT`: `text` (mặc định) hoặc `json` để đẩy log sang hệ thống tập trung.
+#[cfg(not(tarpaulin_include))]
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let format = std::env::var("LOG_FORMAT")
+        .ok()
+        .and_then(|f| f.parse::<LogFormat>().ok())
+        .unwrap_or_default();
+
+    let builder = fmt().with_env_filter(filter).with_target(true);
+    let _ = match format {
+        LogFormat::Json => builder.json().flatten_event(true).with_current_span(true).with_span_list(false).try_init(),
+        LogFormat::Text => builder.try_init(),
+    };
+}
+
+/// Tạo span cho mỗi request HTTP. Các trường `user_id` và `session_id` được điền sau
+/// bởi middleware xác thực và các handler.
+pub fn make_request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        uri = %request.uri().path(),
+        user_id = tracing::field::Empty,
+        session_id = tracing::field::Empty,
+    )
+}
+
+pub fn set_request_id_layer() -> SetRequestIdLayer<MakeRequestUuid> {
+    SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid)
+}
+
+pub fn propagate_request_id_layer() -> PropagateRequestIdLayer {
+    PropagateRequestIdLayer::new(REQUEST_ID_HEADER)
+}
+
+pub fn trace_layer<B>() -> TraceLayer<SharedClassifier<ServerErrorsAsFailures>, MakeSpanFn<B>> {
+    TraceLayer::new_for_http().make_span_with(make_request_span::<B> as MakeSpanFn<B>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_format_parsing() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("text".parse::<LogFormat>().unwrap(), LogFormat::Text);
+        assert_eq!("".parse::<LogFormat>().unwrap(), LogFormat::Text);
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::session::{SessionError, SessionOptions};
+
+pub const MAX_TEMPLATE_NAME_LEN: usize = 128;
+pub const MAX_TEMPLATE_ARGS: usize = 64;
+pub const MAX_TEMPLATE_ENV: usize = 64;
+/// Idle timeout tối đa của template (một năm).
+pub const MAX_IDLE_TIMEOUT_MINS: u64 = 525_600;
+
+/// Profile session do user lưu (`session_templates`), ví dụ "prod logs tail" hay "repo dev shell".
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionTemplate {
+    pub name: String,
+    /// Chương trình chạy thay cho shell; `None` = shell mặc định.
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Thư mục làm việc (đường dẫn tuyệt đối).
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Kích thước ban đầu, dùng tới khi client đầu tiên gửi kích thước của nó.
+    #[serde(default)]
+    pub rows: Option<u16>,
+    #[serde(default)]
+    pub cols: Option<u16>,
+    /// Ghi output thành asciicast (cần `SESSION_RECORDING_DIR`).
+    #[serde(default)]
+    pub recording: bool,
+    /// Ghi đè `SESSION_IDLE_TIMEOUT_MINS` cho session tạo từ template.
+    #[serde(default)]
+    pub idle_timeout_mins: Option<u64>,
+}
+
+impl SessionTemplate {
+    pub fn validate(&self) -> Result<(), SessionError> {
+        let invalid = |reason| Err(SessionError::InvalidTemplate { reason });
+        let name = self.name.trim();
+        if name.is_empty() || name != self.name || name.chars().count() > MAX_TEMPLATE_NAME_LEN || name.contains('/') {
+            return invalid("name must be 1-128 characters without '/' or surrounding spaces");
+        }
+        if self.command.as_ref().is_some_and(|c| c.trim().is_empty()) {
+            return invalid("command must not be empty");
+        }
+        if self.command.is_none() && !self.args.is_empty() {
+            return invalid("args require a command");
+        }
+        if self.args.len() > MAX_TEMPLATE_ARGS {
+            return invalid("at most 64 args are allowed");
+        }
+        if self.cwd.as_ref().is_some_and(|c| !c.starts_with('/')) {
+            return invalid("cwd must be an absolute path");
+        }
+        if self.env.len() > MAX_TEMPLATE_ENV {
+            return invalid("at most 64 env variables are allowed");
+        }
+        if self.env.keys().any(|k| k.is_empty() || k.contains('=') || k.starts_with("BTERMINAL_")) {
+            return invalid("env names must be non-empty, without '=' and not start with BTERMINAL_");
+        }
+        let strings = self.command.iter().chain(&self.args).chain(&self.cwd).chain(self.env.keys()).chain(self.env.values());
+        if strings.into_iter().any(|s| s.contains('\0')) {
+            return invalid("values must not contain NUL bytes");
+        }
+        match (self.rows, self.cols) {
+            (None, None) => {}
+            (Some(rows), Some(cols)) if rows > 0 && cols > 0 => {}
+            _ => return invalid("rows and cols must be set together and be positive"),
+        }
+        if self.idle_timeout_mins.is_some_and(|m| m == 0 || m > MAX_IDLE_TIMEOUT_MINS) {
+            return invalid("idle_timeout_mins must be between 1 and 525600");
+        }
+        Ok(())
+    }
+
+    /// Áp dụng template lên `options`; các trường trong request tạo session vẫn được ưu tiên.
+    #[must_use]
+    pub fn apply(&self, mut options: SessionOptions) -> SessionOptions {
+        if let Some(command) = &self.command {
+            options = options.command(command.clone(), self.args.clone());
+        }
+        if let Some(cwd) = &self.cwd {
+            options = options.cwd(cwd.clone());
+        }
+        options = options.envs(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if let (Some(rows), Some(cols)) = (self.rows, self.cols) {
+            options = options.size(rows, cols);
+        }
+        if let Some(mins) = self.idle_timeout_mins {
+            options = options.idle_timeout(Duration::from_mins(mins));
+        }
+        options.recording(self.recording)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> SessionTemplate {
+        SessionTemplate {
+            name: "prod logs tail".to_string(),
+            command: Some("tail".to_string()),
+            args: vec!["-f".to_string(), "/var/log/syslog".to_string()],
+            cwd: Some("/var/log".to_string()),
+            env: BTreeMap::from([("LESS".to_string(), "-R".to_string())]),
+            rows: Some(40),
+            cols: Some(120),
+            recording: true,
+            idle_timeout_mins: Some(30),
+        }
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert_eq!(template().validate(), Ok(()));
+        assert_eq!(SessionTemplate { name: "shell".to_string(), ..SessionTemplate::default() }.validate(), Ok(()));
+
+        let invalid = [
+            SessionTemplate { name: String::new(), ..template() },
+            SessionTemplate { name: " padded".to_string(), ..template() },
+            SessionTemplate { name: "a/b".to_string(), ..template() },
+            SessionTemplate { command: Some(" ".to_string()), ..template() },
+            SessionTemplate { command: None, ..template() },
+            SessionTemplate { cwd: Some("relative".to_string()), ..template() },
+            SessionTemplate { env: BTreeMap::from([("A=B".to_string(), String::new())]), ..template() },
+            SessionTemplate { env: BTreeMap::from([("BTERMINAL_USER".to_string(), "root".to_string())]), ..template() },
+            SessionTemplate { args: vec!["a\0b".to_string()], ..template() },
+            SessionTemplate { cols: None, ..template() },
+            SessionTemplate { rows: Some(0), ..template() },
+            SessionTemplate { idle_timeout_mins: Some(0), ..template() },
+            SessionTemplate { idle_timeout_mins: Some(u64::MAX), ..template() },
+        ];
+        for t in invalid {
+            assert!(matches!(t.validate(), Err(SessionError::InvalidTemplate { .. })), "{t:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_apply_template() {
+        let options = template().apply(SessionOptions::new("logs".to_string()));
+        assert_eq!(options.command.as_deref(), Some("tail"));
+        assert_eq!(options.args, ["-f", "/var/log/syslog"]);
+        assert_eq!(options.cwd.as_deref(), Some("/var/log"));
+        assert_eq!(options.env, [("LESS".to_string(), "-R".to_string())]);
+        assert_eq!(options.size, Some((40, 120)));
+        assert!(options.recording);
+        assert_eq!(options.idle_timeout, Some(Duration::from_mins(30)));
+    }
+}
@@ -1,13 +1,16 @@
 use ax_ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::{
-    extract::{ws as ax_ws, State},
+    extract::{ws as ax_ws, Query, State},
     response::IntoResponse,
 };
-use futures_util::{sink::SinkExt, stream::StreamExt};
+use futures_util::{sink::SinkExt, stream::{SplitSink, StreamExt}};
 use std::sync::Arc;
 use serde::Deserialize;
-use crate::session::Session;
+use crate::history::History;
+use crate::output::Received;
+use crate::session::{ClientGuard, Output, Session};
 use crate::AppState;
+use tracing::Instrument;
 
 #[derive(Deserialize)]
 #[serde(tag = "type", content = "data")]
@@ -16,111 +19,155 @@ enum ClientMessage {
     Resize { rows: u16, cols: u16 },
 }
 
+#[derive(Deserialize)]
+pub struct WsQuery {
+    /// Offset output client đã nhận tới (giá trị `Sync` cộng số byte nhận sau đó); server chỉ gửi phần còn thiếu.
+    since: Option<u64>,
+}
+
+/// Phần output gửi để client bắt kịp: các byte còn thiếu kể từ `since`, hoặc toàn bộ lịch sử
+/// trong bộ nhớ nếu phần đó đã bị đẩy khỏi bộ nhớ (khi đó client phải xoá màn hình trước, `reset`).
+struct CatchUp {
+    data: Vec<u8>,
+    reset: bool,
+    /// Offset ngay sau `data`.
+    offset: u64,
+}
+
+impl CatchUp {
+    fn new(history: &History, since: Option<u64>) -> Self {
+        let missed = since.and_then(|since| history.since(since));
+        let reset = since.is_some() && missed.is_none();
+        Self { data: missed.unwrap_or_else(|| history.snapshot()), reset, offset: history.end_offset() }
+    }
+
+    /// Gửi `Reset` (nếu cần) rồi dữ liệu; tin nhắn `Sync` gửi riêng bằng `sync_message`.
+    async fn send(self, sender: &mut SplitSink<WebSocket, Message>) -> Result<(), axum::Error> {
+        if self.reset {
+            sender.send(Message::Text(r#"{"type": "Reset"}"#.into())).await?;
+        }
+        if !self.data.is_empty() {
+            sender.send(Message::Binary(self.data.into())).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Báo offset output hiện tại; các byte Binary nhận sau tin nhắn này nối tiếp từ `offset`.
+fn sync_message(offset: u64) -> Message {
+    Message::Text(format!(r#"{{"type": "Sync", "data": {{"offset": {offset}}}}}"#).into())
+}
+
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
     axum::extract::Path(session_id): axum::extract::Path<String>,
+    Query(query): Query<WsQuery>,
 ) -> impl IntoResponse {
+    tracing::Span::current().record("session_id", session_id.as_str());
     let session = state.registry.get_session(&session_id);
 
     session.map_or_else(|| {
-        println!("Session not found: {session_id}");
+        tracing::warn!("session not found");
         "Session not found".into_response()
     }, |s| {
-        println!("Joining session: {session_id}");
-        ws.on_upgrade(move |socket| handle_socket(socket, s))
+        // Giữ slot trước khi upgrade; slot được trả lại khi guard bị drop (kể cả khi upgrade thất bại).
+        let client = match s.try_attach_client(state.registry.limits().max_clients_per_session) {
+            Ok(guard) => guard,
+            Err(err) => {
+                tracing::warn!(error = %err, "rejecting WebSocket client");
+                return err.into_response();
+            }
+        };
+        tracing::info!("joining session");
+        // Giữ span của request để các log trong suốt vòng đời WS vẫn mang request_id/user_id.
+        let span = tracing::info_span!("ws", session_id = %session_id, client_id = tracing::field::Empty);
+        ws.on_upgrade(move |socket| handle_socket(socket, s, client, query.since).instrument(span))
     })
 }
 
-async fn handle_socket(socket: WebSocket, session: Session) {
+async fn handle_socket(socket: WebSocket, session: Session, _client: ClientGuard, since: Option<u64>) {
     let client_id = uuid::Uuid::new_v4();
+    tracing::Span::current().record("client_id", tracing::field::display(client_id));
+    tracing::info!(?since, "client connected");
     let (mut sender, mut receiver) = socket.split();
 
-    // Send history first
-    let history_data = {
+    // Đăng ký nhận output và chụp lịch sử trong cùng một lần giữ khóa (monitor phát output khi giữ khóa),
+    // nên giữa lịch sử và output trực tiếp không mất hay lặp byte nào.
+    let (output, catch_up) = {
         let history = session.history.lock().unwrap();
-        if history.is_empty() {
-            None
-        } else {
-            Some(history.clone())
-        }
+        (session.output.subscribe(), CatchUp::new(&history, since))
     };
+    let mut next = catch_up.offset;
 
-    if let Some(data) = history_data
-        && let Err(e) = sender.send(Message::Binary(data.into())).await {
+    // Send history first
+    if let Err(e) = catch_up.send(&mut sender).await {
         #[cfg(not(tarpaulin_include))]
-        println!("Error sending history: {e}");
+        tracing::warn!(error = %e, "failed to send history");
         return;
     }
 
     // Gửi kích thước PTY hiện tại cho client mới
-    let current_pty_size = {
-        let sizes = session.client_sizes.lock().unwrap();
-        if sizes.is_empty() {
-            None
-        } else {
-            let mut min_rows = u16::MAX;
-            let mut min_cols = u16::MAX;
-            for (r, c) in sizes.values() {
-                if *r < min_rows { min_rows = *r; }
-                if *c < min_cols { min_cols = *c; }
-            }
-            if min_rows != u16::MAX && min_cols != u16::MAX {
-                Some((min_rows, min_cols))
-            } else {
-                None
-            }
-        }
-    };
-
-    if let Some((rows, cols)) = current_pty_size {
+    if let Some((rows, cols)) = session.current_size() {
         let msg_str = format!(r#"{{"type": "SetSize", "data": {{"rows": {rows}, "cols": {cols}}}}}"#);
         if let Err(e) = sender.send(Message::Binary(msg_str.into_bytes().into())).await {
             #[cfg(not(tarpaulin_include))]
-            println!("Error sending initial PTY size: {e}");
+            tracing::warn!(error = %e, "failed to send initial PTY size");
             return;
         }
     }
 
-    let mut rx = session.broadcast_tx.subscribe();
-    let pty = session.pty_manager.clone();
+    if let Err(e) = sender.send(sync_message(next)).await {
+        #[cfg(not(tarpaulin_include))]
+        tracing::warn!(error = %e, "failed to send output offset");
+        return;
+    }
+
+    let pty = session.backend.clone();
     let session_clone = session.clone();
+    let history = session.history.clone();
 
     // Spawn a task to forward PTY output to WebSocket
     let mut send_task = tokio::spawn(async move {
         loop {
-            match rx.recv().await {
-                Ok(data) => {
-                    if data.is_empty() {
-                        break; // PTY ended
-                    }
-                    
-                    // Kiểm tra xem đây có phải là tin nhắn điều khiển JSON không (SetSize, v.v.)
-                    if data.starts_with(br#"{"type":"#)
-                        && let Ok(text) = String::from_utf8(data.clone()) {
-                        if let Err(e) = sender.send(Message::Text(text.into())).await {
-                            println!("WS send error (text): {e}");
-                            return;
-                        }
+            let result = match output.recv().await {
+                Received::Output(Output::Data { offset, data }) => {
+                    // Bỏ phần client đã nhận qua lịch sử
+                    let skip = usize::try_from(next.saturating_sub(offset)).unwrap_or(usize::MAX);
+                    if skip >= data.len() {
                         continue;
                     }
-
-                    let bin_data: Vec<u8> = data;
-                    if let Err(e) = sender.send(Message::Binary(bin_data.into())).await {
-                        println!("WS send error (binary): {e}");
-                        return;
-                    }
+                    next = offset + data.len() as u64;
+                    sender.send(Message::Binary(data.slice(skip..))).await
                 }
-                Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
-                    println!("WS forwarder lagged by {} messages", n);
+                Received::Output(Output::Control(text)) => sender.send(Message::Text(text.into())).await,
+                Received::Output(Output::Exit) => break,
+                Received::Overflowed(dropped) => {
+                    // Hàng đợi của client đã tràn: đồng bộ lại từ lịch sử thay vì bỏ qua output đã mất
+                    // (màn hình client sẽ bị sai)
+                    tracing::warn!(dropped, offset = next, "WS client fell behind, resynchronizing");
+                    crate::metrics::record_lag("ws", dropped);
+                    let catch_up = {
+                        let history = history.lock().unwrap();
+                        output.resume();
+                        CatchUp::new(&history, Some(next))
+                    };
+                    next = catch_up.offset;
+                    match catch_up.send(&mut sender).await {
+                        Ok(()) => sender.send(sync_message(next)).await,
+                        Err(e) => Err(e),
+                    }
                 }
-                Err(_) => break, // Channel closed
+            };
+            if let Err(e) = result {
+                tracing::debug!(error = %e, "WS send error");
+                return;
             }
         }
-        
-        // If we reach here, it means the broadcast channel is closed or we got an empty signal
+
+        // If we reach here, it means the backend exited
         let _ = sender.send(Message::Text(r#"{"type": "Exit"}"#.into())).await;
-    });
+    }.in_current_span());
 
     // Handle incoming messages from WebSocket
     let session_for_recv = session.clone();
@@ -130,9 +177,10 @@ async fn handle_socket(socket: WebSocket, session: Session) {
                 && let Ok(client_msg) = serde_json::from_str::<ClientMessage>(&text) {
                 match client_msg {
                     ClientMessage::Input(data) => {
+                        session_for_recv.record_input();
                         if let Err(e) = pty.write(data.as_bytes()) {
                             #[cfg(not(tarpaulin_include))]
-                            println!("PTY write error: {e}");
+                            tracing::error!(error = %e, "PTY write error");
                         }
                     }
                     ClientMessage::Resize { rows, cols } => {
@@ -141,7 +189,7 @@ async fn handle_socket(socket: WebSocket, session: Session) {
                 }
             }
         }
-    });
+    }.in_current_span());
 
     // If either task fails, abort the other
     tokio::select! {
@@ -151,6 +199,7 @@ async fn handle_socket(socket: WebSocket, session: Session) {
 
     // Clean up client size on disconnect
     session_clone.remove_client(client_id);
+    tracing::info!("client disconnected");
 }
 
 #[cfg(test)]
@@ -160,13 +209,14 @@ mod tests {
     use axum::{routing::get, Router};
     use crate::session::SessionRegistry;
     use crate::db::Db;
+    use crate::config::Config;
     use tokio::sync::broadcast;
 
     async fn setup_state() -> Arc<AppState> {
         let (tx, _) = broadcast::channel(10);
         let registry = Arc::new(SessionRegistry::new(tx.clone()));
         let db = Db::new("sqlite::memory:").await.unwrap();
-        Arc::new(AppState { registry, tx, db })
+        Arc::new(AppState { registry, tx, db, config: Config::default() })
     }
 
     #[test]
@@ -227,12 +277,12 @@ mod tests {
 
         let state = setup_state().await;
         let session_id = "history-test".to_string();
-        let session = state.registry.create_session(session_id.clone());
+        let session = state.registry.create_session(session_id.clone()).unwrap();
         
         // Add some history
         {
             let mut history = session.history.lock().unwrap();
-            history.extend_from_slice(b"old data");
+            history.push(b"old data");
         }
 
         let app = Router::new()
@@ -255,6 +305,52 @@ mod tests {
         assert_eq!(msg.into_data().as_ref(), b"old data");
     }
 
+    #[tokio::test]
+    async fn test_ws_client_limit_and_slot_release() {
+        use tokio_tungstenite::connect_async;
+        use tokio::net::TcpListener;
+        use crate::config::SessionLimits;
+
+        let (tx, _) = broadcast::channel(10);
+        let limits = SessionLimits { max_clients_per_session: Some(1), ..SessionLimits::default() };
+        let registry = Arc::new(SessionRegistry::with_limits(tx.clone(), limits));
+        let db = Db::new("sqlite::memory:").await.unwrap();
+        let state = Arc::new(AppState { registry, tx, db, config: Config::default() });
+        let session = state.registry.create_session("limit-test".to_string()).unwrap();
+
+        let app = Router::new()
+            .route("/ws/{session_id}", get(ws_handler))
+            .with_state(state);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let url = format!("ws://{addr}/ws/limit-test");
+
+        let (first, _) = connect_async(url.clone()).await.expect("first client should connect");
+        assert_eq!(session.client_count(), 1);
+
+        match connect_async(url.clone()).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(resp)) => {
+                assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+            }
+            other => panic!("Expected 429 for second client, got {other:?}"),
+        }
+
+        // Đóng client đầu tiên: slot phải được trả lại
+        drop(first);
+        for _ in 0..100 {
+            if session.client_count() == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(session.client_count(), 0);
+        let (_third, _) = connect_async(url).await.expect("slot should be released after disconnect");
+        assert_eq!(session.client_count(), 1);
+    }
+
     #[tokio::test]
     async fn test_ws_initial_pty_size_sent() {
         use tokio_tungstenite::connect_async;
@@ -263,7 +359,7 @@ mod tests {
 
         let state = setup_state().await;
         let session_id = "size-test".to_string();
-        let session = state.registry.create_session(session_id.clone());
+        let session = state.registry.create_session(session_id.clone()).unwrap();
         
         // Simulate a client already connected and set PTY size
         let existing_client_id = Uuid::new_v4();
@@ -302,4 +398,47 @@ mod tests {
                     panic!("Expected a Binary message for SetSize, got {:?}", msg);
                 }
     }
+
+    #[tokio::test]
+    async fn test_ws_resume_since_offset() {
+        use tokio_tungstenite::connect_async;
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+        use tokio::net::TcpListener;
+        use crate::session::SessionOptions;
+
+        let state = setup_state().await;
+        let options = SessionOptions::new("resume".to_string()).history_size(4).command("sleep", vec!["30".to_string()]);
+        let session = state.registry.create_session(options).unwrap();
+        session.history.lock().unwrap().push(b"abcdef");
+
+        let app = Router::new()
+            .route("/ws/{session_id}", get(ws_handler))
+            .with_state(state.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let sync = |offset: u64| WsMessage::Text(format!(r#"{{"type": "Sync", "data": {{"offset": {offset}}}}}"#).into());
+
+        // Chỉ nhận phần còn thiếu, sau đó là output trực tiếp nối tiếp offset
+        let (mut ws, _) = connect_async(format!("ws://{addr}/ws/resume?since=3")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), WsMessage::Binary(b"def".to_vec().into()));
+        assert_eq!(ws.next().await.unwrap().unwrap(), sync(6));
+        session.broadcast_tx.send(b"gh".to_vec()).unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), WsMessage::Binary(b"gh".to_vec().into()));
+
+        // Đã nhận đủ: không gửi lại gì
+        let (mut ws, _) = connect_async(format!("ws://{addr}/ws/resume?since=8")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), sync(8));
+
+        // Offset đã bị đẩy khỏi bộ nhớ: Reset rồi gửi lại toàn bộ lịch sử
+        let (mut ws, _) = connect_async(format!("ws://{addr}/ws/resume?since=1")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), WsMessage::Text(r#"{"type": "Reset"}"#.into()));
+        assert_eq!(ws.next().await.unwrap().unwrap(), WsMessage::Binary(b"efgh".to_vec().into()));
+        assert_eq!(ws.next().await.unwrap().unwrap(), sync(8));
+
+        state.registry.terminate_session("resume");
+        assert_eq!(ws.next().await.unwrap().unwrap(), WsMessage::Text(r#"{"type": "Exit"}"#.into()));
+    }
 }
@@ -1,4 +1,4 @@
-use bterminal::{create_app, session::SessionRegistry, db::Db, auth};
+use bterminal::{create_app, config::Config, session::SessionRegistry, db::Db, auth};
 use axum::{
     body::Body,
     http::{Request, StatusCode},
@@ -17,7 +17,7 @@ async fn test_auth_flow() {
     let hash = auth::hash_password("password123").unwrap();
     db.create_user("testuser", &hash, "member").await.unwrap();
 
-    let app = create_app(tx, registry, db);
+    let app = create_app(tx, registry, db, Config::default());
 
     // 1. Fail without auth
     let response = app.clone()
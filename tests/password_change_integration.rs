@@ -1,4 +1,4 @@
-use bterminal::{create_app, session::SessionRegistry, db::Db, auth};
+use bterminal::{create_app, config::Config, session::SessionRegistry, db::Db, auth};
 use axum::{
     body::Body,
     http::{Request, StatusCode},
@@ -18,7 +18,7 @@ async fn test_forced_password_change_flow() {
     let hash = auth::hash_password("initial_pass").unwrap();
     db.create_user("newuser", &hash, "member").await.unwrap();
 
-    let app = create_app(tx, registry, db);
+    let app = create_app(tx, registry, db, Config::default());
 
     // 1. Login
     let login_body = serde_json::to_string(&serde_json::json!({
@@ -59,7 +59,7 @@ async fn test_forbidden_until_password_changed() {
     let hash = auth::hash_password("initial").unwrap();
     db.create_user("user1", &hash, "member").await.unwrap();
 
-    let app = create_app(tx, registry, db);
+    let app = create_app(tx, registry, db, Config::default());
 
     // Login
     let login_body = serde_json::to_string(&serde_json::json!({
@@ -6,15 +6,18 @@ use axum::{routing::get, Router};
 use bterminal::api::{events_handler, create_session, CreateSessionRequest};
 use tokio::sync::broadcast;
 use futures_util::StreamExt;
-use axum::extract::{State, Json};
+use axum::extract::{Extension, State, Json};
+use bterminal::auth::CurrentUser;
+use bterminal::db::User;
 use bterminal::db::Db;
+use bterminal::config::Config;
 
 #[tokio::test]
 async fn test_sse_events_flow() {
     let (tx, _) = broadcast::channel(10);
     let registry = Arc::new(SessionRegistry::new(tx.clone()));
     let db = Db::new("sqlite::memory:").await.unwrap();
-    let state = Arc::new(AppState { registry: registry.clone(), tx: tx.clone(), db });
+    let state = Arc::new(AppState { registry: registry.clone(), tx: tx.clone(), db, config: Config::default() });
 
     let app = Router::new()
         .route("/api/events", get(events_handler))
@@ -38,8 +41,16 @@ async fn test_sse_events_flow() {
         .bytes_stream();
 
     // 2. Trigger an event (SessionCreated)
-    let payload = Json(CreateSessionRequest { id: "sse-test".to_string() });
-    create_session(State(state), payload).await;
+    let payload = Json(CreateSessionRequest { id: Some("sse-test".to_string()), ..Default::default() });
+    let user = Extension(CurrentUser(User {
+        id: 1,
+        username: "sse".to_string(),
+        password_hash: String::new(),
+        role: "member".to_string(),
+        must_change_password: false,
+        unix_user: None,
+    }));
+    create_session(State(state), user, payload).await;
 
     // 3. Verify event received in SSE stream
     let first_chunk = stream.next().await.unwrap().unwrap();
@@ -56,7 +67,7 @@ async fn test_sse_automatic_exit_flow() {
     let (tx, _) = broadcast::channel(10);
     let registry = Arc::new(SessionRegistry::new(tx.clone()));
     let db = Db::new("sqlite::memory:").await.unwrap();
-    let state = Arc::new(AppState { registry: registry.clone(), tx: tx.clone(), db });
+    let state = Arc::new(AppState { registry: registry.clone(), tx: tx.clone(), db, config: Config::default() });
 
     let app = Router::new()
         .route("/api/events", get(events_handler))
@@ -75,7 +86,7 @@ async fn test_sse_automatic_exit_flow() {
     let mut stream = client.get(url).send().await.unwrap().bytes_stream();
 
     // 2. Create a session (triggers monitor_session)
-    let session = registry.create_session("auto-exit-test".to_string());
+    let session = registry.create_session("auto-exit-test".to_string()).unwrap();
     
     // 3. Simulate PTY termination
     session.broadcast_tx.send(Vec::new()).unwrap();
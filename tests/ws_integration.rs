@@ -7,6 +7,7 @@ use bterminal::AppState;
 use axum::{routing::get, Router};
 use bterminal::ws::ws_handler;
 use bterminal::db::Db;
+use bterminal::config::Config;
 use tokio::sync::broadcast;
 
 #[tokio::test]
@@ -14,10 +15,10 @@ async fn test_websocket_flow() {
     let (tx, _) = broadcast::channel(10);
     let registry = Arc::new(SessionRegistry::new(tx.clone()));
     let db = Db::new("sqlite::memory:").await.unwrap();
-    let state = Arc::new(AppState { registry: registry.clone(), tx, db });
+    let state = Arc::new(AppState { registry: registry.clone(), tx, db, config: Config::default() });
     
     let session_id = "ws-test".to_string();
-    let _ = registry.create_session(session_id.clone());
+    registry.create_session(session_id.clone()).unwrap();
 
     let app = Router::new()
         .route("/ws/{session_id}", get(ws_handler))